@@ -0,0 +1,99 @@
+use super::{BackupType, FlashChip};
+
+/// Nintendo's save libraries embed an ID string like "FLASH1M_V103" in the ROM, word aligned.
+const LIBRARY_IDS: [(&[u8], BackupType); 6] = [
+    (b"EEPROM_V", BackupType::Eeprom),
+    (b"SRAM_V", BackupType::Sram),
+    (b"SRAM_F_V", BackupType::Sram),
+    (b"FLASH_V", BackupType::Flash(FlashChip::Panasonic)),
+    (b"FLASH512_V", BackupType::Flash(FlashChip::Panasonic)),
+    (b"FLASH1M_V", BackupType::Flash(FlashChip::Macronix128K)),
+];
+
+/// Games whose library ID is missing or misleading, by game code.
+const GAME_CODES: [(&[u8; 4], BackupType); 16] = [
+    // Iridion II and Top Gun: Combat Zones break if they find any save memory.
+    (b"AI2E", BackupType::None),
+    (b"AI2P", BackupType::None),
+    (b"A2YE", BackupType::None),
+    // Super Monkey Ball Jr.
+    (b"ALUE", BackupType::Eeprom),
+    (b"ALUP", BackupType::Eeprom),
+    // Dragon Ball Z: The Legacy of Goku II
+    (b"ALFE", BackupType::Eeprom),
+    (b"ALFP", BackupType::Eeprom),
+    // Advance Wars and Advance Wars 2
+    (b"AWRE", BackupType::Flash(FlashChip::Panasonic)),
+    (b"AW2E", BackupType::Flash(FlashChip::Panasonic)),
+    // Super Mario Advance 4
+    (b"AX4E", BackupType::Flash(FlashChip::Macronix128K)),
+    (b"AX4P", BackupType::Flash(FlashChip::Macronix128K)),
+    // Pokémon Ruby, Sapphire, Emerald, FireRed and LeafGreen check for one of the 128 KiB chips.
+    (b"AXVE", BackupType::Flash(FlashChip::Macronix128K)),
+    (b"AXPE", BackupType::Flash(FlashChip::Macronix128K)),
+    (b"BPEE", BackupType::Flash(FlashChip::Macronix128K)),
+    (b"BPRE", BackupType::Flash(FlashChip::Macronix128K)),
+    (b"BPGE", BackupType::Flash(FlashChip::Macronix128K)),
+];
+
+/// Choose the save memory for a ROM, from its game code or the library ID string.
+pub fn detect(rom: &[u8]) -> BackupType {
+    let game_code = rom.get(0xac..0xb0);
+    if let Some(&(_, backup_type)) = GAME_CODES
+        .iter()
+        .find(|(code, _)| Some(&code[..]) == game_code)
+    {
+        return backup_type;
+    }
+
+    (0..rom.len())
+        .step_by(4)
+        .find_map(|offset| {
+            LIBRARY_IDS
+                .iter()
+                .find(|(id, _)| rom[offset..].starts_with(id))
+                .map(|&(_, backup_type)| backup_type)
+        })
+        .unwrap_or(BackupType::None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_with(offset: usize, bytes: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 0x1000];
+        rom[offset..offset + bytes.len()].copy_from_slice(bytes);
+        rom
+    }
+
+    #[test]
+    fn detects_library_ids() {
+        assert_eq!(detect(&rom_with(0x400, b"SRAM_V113")), BackupType::Sram);
+        assert_eq!(detect(&rom_with(0x400, b"EEPROM_V124")), BackupType::Eeprom);
+        assert_eq!(
+            detect(&rom_with(0x400, b"FLASH512_V131")),
+            BackupType::Flash(FlashChip::Panasonic)
+        );
+        assert_eq!(
+            detect(&rom_with(0x400, b"FLASH1M_V103")),
+            BackupType::Flash(FlashChip::Macronix128K)
+        );
+        assert_eq!(detect(&rom_with(0x400, b"FLASH")), BackupType::None);
+    }
+
+    #[test]
+    fn game_code_overrides_library_id() {
+        let mut rom = rom_with(0x400, b"EEPROM_V122");
+        rom[0xac..0xb0].copy_from_slice(b"AI2E");
+        assert_eq!(detect(&rom), BackupType::None);
+    }
+
+    #[test]
+    fn test_roms_have_no_save() {
+        assert_eq!(
+            detect(include_bytes!("../../../tests/roms/panda.gba")),
+            BackupType::None
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::cell::Cell;
+
+use crate::utils::AddressableBits;
+
+const BLOCK_BITS: u32 = 64;
+// Reads start with 4 junk bits before the data.
+const READ_BITS: u32 = 4 + BLOCK_BITS;
+
+/// Serial EEPROM, accessed one bit at a time through bit 0 of halfword reads and writes,
+/// normally with DMA3.
+///
+/// Requests start with two bits, 0b11 to read or 0b10 to write, followed by a block address and
+/// for writes the 64 data bits, and end with a 0 bit. Data is sent most significant bit first.
+pub struct Eeprom {
+    data: Vec<u8>,
+    address_bits: u32,
+    // False until the size has been worked out from the first request.
+    size_known: bool,
+
+    // Bits of the request being received.
+    request: u128,
+    request_len: u32,
+
+    // The block being read and how many bits of it have been sent. Reads advance this, but the
+    // bus is only borrowed immutably when reading.
+    read: Cell<Option<(usize, u32)>>,
+}
+
+impl Eeprom {
+    /// Create a 512 byte EEPROM with 6 bit addresses or an 8 KiB one with 14 bit addresses.
+    pub fn new(size: usize) -> Self {
+        Self {
+            data: vec![0xff; size],
+            address_bits: if size > 0x200 { 14 } else { 6 },
+            size_known: true,
+            request: 0,
+            request_len: 0,
+            read: Cell::new(None),
+        }
+    }
+
+    /// Create an EEPROM whose size is decided by the length of the first request, since the
+    /// ROM doesn't say which size it expects.
+    pub fn with_unknown_size() -> Self {
+        Self {
+            size_known: false,
+            ..Self::new(0x2000)
+        }
+    }
+
+    pub fn size_known(&self) -> bool {
+        self.size_known
+    }
+
+    /// Set the size from the number of bits in a request written by DMA, if it isn't known yet.
+    pub fn detect_size(&mut self, request_bits: u32) {
+        let size = match request_bits {
+            // Read and write requests with 6 bit addresses.
+            9 | 73 => 0x200,
+            // Read and write requests with 14 bit addresses.
+            17 | 81 => 0x2000,
+            _ => return,
+        };
+        if !self.size_known {
+            self.set_size(size);
+        }
+    }
+
+    pub fn set_size(&mut self, size: usize) {
+        self.data.resize(size, 0xff);
+        self.address_bits = if size > 0x200 { 14 } else { 6 };
+        self.size_known = true;
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    fn blocks(&self) -> usize {
+        self.data.len() / 8
+    }
+
+    fn bit(&self, block: usize, index: u32) -> u16 {
+        let byte = self.data[8 * block + index as usize / 8];
+        u16::from(byte.bit(7 - index as usize % 8))
+    }
+
+    /// The bit the next read will see, without moving on to the one after it.
+    pub fn peek(&self) -> u16 {
+        match self.read.get() {
+            Some((_, 0..=3)) => 0,
+            Some((block, sent)) => self.bit(block, sent - 4),
+            // Writes finish instantly, so the chip always reports that it's ready.
+            None => 1,
+        }
+    }
+
+    /// Move on to the next bit of a read, after the current one has been seen by the bus.
+    pub fn advance(&self) {
+        if let Some((block, sent)) = self.read.get() {
+            self.read
+                .set((sent + 1 < READ_BITS).then_some((block, sent + 1)));
+        }
+    }
+
+    pub fn write(&mut self, value: u16) {
+        self.request = (self.request << 1) | u128::from(value & 1);
+        self.request_len += 1;
+        if self.request_len < 2 {
+            return;
+        }
+
+        let kind = (self.request >> (self.request_len - 2)) & 0b11;
+        let read_len = 2 + self.address_bits + 1;
+        let write_len = read_len + BLOCK_BITS;
+        match (self.request_len, kind) {
+            (2, 0b00 | 0b01) => {}
+            (len, 0b11) if len == read_len => {
+                let block = self.block(self.request >> 1);
+                self.read.set(Some((block, 0)));
+            }
+            (len, 0b10) if len == write_len => {
+                let block = self.block(self.request >> (BLOCK_BITS + 1));
+                let data = (self.request >> 1) as u64;
+                self.data[8 * block..8 * block + 8].copy_from_slice(&data.to_be_bytes());
+            }
+            _ => return,
+        }
+        // The request is finished, or wasn't a valid one.
+        self.request = 0;
+        self.request_len = 0;
+    }
+
+    /// The block addressed by the low address bits of a request.
+    fn block(&self, request: u128) -> usize {
+        let address = request as usize & ((1 << self.address_bits) - 1);
+        // Only the low 10 bits of the 14 bit addresses are used.
+        address & (self.blocks() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(eeprom: &mut Eeprom, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            eeprom.write(((value >> i) & 1) as u16);
+        }
+    }
+
+    fn read(eeprom: &Eeprom) -> u16 {
+        let bit = eeprom.peek();
+        eeprom.advance();
+        bit
+    }
+
+    fn receive(eeprom: &Eeprom) -> u64 {
+        for _ in 0..4 {
+            assert_eq!(read(eeprom), 0);
+        }
+        (0..64).fold(0, |data, _| (data << 1) | u64::from(read(eeprom)))
+    }
+
+    #[test]
+    fn write_then_read_block() {
+        let mut eeprom = Eeprom::new(0x2000);
+        send(&mut eeprom, 0b10, 2);
+        send(&mut eeprom, 0x3ff, 14);
+        send(&mut eeprom, 0x0123_4567_89ab_cdef, 64);
+        send(&mut eeprom, 0, 1);
+        assert_eq!(
+            &eeprom.data()[0x1ff8..],
+            &0x0123_4567_89ab_cdefu64.to_be_bytes()
+        );
+
+        send(&mut eeprom, 0b11, 2);
+        send(&mut eeprom, 0x3ff, 14);
+        send(&mut eeprom, 0, 1);
+        assert_eq!(receive(&eeprom), 0x0123_4567_89ab_cdef);
+        // Once the block has been read the chip reports that it's ready.
+        assert_eq!(read(&eeprom), 1);
+    }
+
+    #[test]
+    fn size_is_detected_from_request_length() {
+        let mut eeprom = Eeprom::with_unknown_size();
+        eeprom.detect_size(9);
+        assert!(eeprom.size_known());
+        assert_eq!(eeprom.data().len(), 0x200);
+
+        // Only the first request decides the size.
+        eeprom.detect_size(81);
+        assert_eq!(eeprom.data().len(), 0x200);
+    }
+
+    #[test]
+    fn small_eeprom_uses_6_bit_addresses() {
+        let mut eeprom = Eeprom::new(0x200);
+        send(&mut eeprom, 0b10, 2);
+        send(&mut eeprom, 1, 6);
+        send(&mut eeprom, 0xffff_0000_ffff_0000, 64);
+        send(&mut eeprom, 0, 1);
+
+        send(&mut eeprom, 0b11, 2);
+        send(&mut eeprom, 1, 6);
+        send(&mut eeprom, 0, 1);
+        assert_eq!(receive(&eeprom), 0xffff_0000_ffff_0000);
+    }
+}
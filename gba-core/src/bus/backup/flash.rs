@@ -0,0 +1,232 @@
+/// Flash chips found in cartridges. Games check the chip ID to decide which write and erase
+/// sequences to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashChip {
+    /// 64 KiB Panasonic MN63F805MNP.
+    Panasonic,
+    /// 64 KiB Atmel AT29LV512, which is written a 128 byte page at a time.
+    Atmel,
+    /// 64 KiB Macronix MX29L512.
+    Macronix64K,
+    /// 128 KiB Macronix MX29L010.
+    Macronix128K,
+    /// 128 KiB Sanyo LE26FV10N1TS.
+    Sanyo,
+}
+
+impl FlashChip {
+    /// The manufacturer and device IDs returned in ID mode.
+    pub fn id(&self) -> [u8; 2] {
+        match *self {
+            Self::Panasonic => [0x32, 0x1b],
+            Self::Atmel => [0x1f, 0x3d],
+            Self::Macronix64K => [0xc2, 0x1c],
+            Self::Macronix128K => [0xc2, 0x09],
+            Self::Sanyo => [0x62, 0x13],
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match *self {
+            Self::Panasonic | Self::Atmel | Self::Macronix64K => 0x10000,
+            Self::Macronix128K | Self::Sanyo => 0x20000,
+        }
+    }
+}
+
+const BANK_SIZE: usize = 0x10000;
+const SECTOR_SIZE: usize = 0x1000;
+const ATMEL_PAGE_SIZE: usize = 0x80;
+
+/// Progress through a command sequence. Every command starts with 0xAA written to 0x5555
+/// followed by 0x55 written to 0x2AAA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ready,
+    Unlock1,
+    Unlock2,
+    /// The next write stores a single byte.
+    Write,
+    /// Writes fill a 128 byte page, erasing it first.
+    AtmelWrite {
+        remaining: usize,
+    },
+    /// The next write to 0x0000 selects the 64 KiB bank.
+    BankSwitch,
+}
+
+pub struct Flash {
+    chip: FlashChip,
+    data: Vec<u8>,
+    state: State,
+    id_mode: bool,
+    // Set by command 0x80, which must come before an erase command.
+    erase_armed: bool,
+    bank: usize,
+}
+
+impl Flash {
+    pub fn new(chip: FlashChip) -> Self {
+        Self {
+            chip,
+            data: vec![0xff; chip.size()],
+            state: State::Ready,
+            id_mode: false,
+            erase_armed: false,
+            bank: 0,
+        }
+    }
+
+    pub fn chip(&self) -> FlashChip {
+        self.chip
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Read from an offset into the 64 KiB flash window.
+    pub fn read(&self, offset: usize) -> u8 {
+        if self.id_mode && offset < 2 {
+            self.chip.id()[offset]
+        } else {
+            self.data[self.bank * BANK_SIZE + offset]
+        }
+    }
+
+    /// Write to an offset into the 64 KiB flash window.
+    pub fn write(&mut self, offset: usize, value: u8) {
+        self.state = match (self.state, offset, value) {
+            (State::Write, _, _) => {
+                self.data[self.bank * BANK_SIZE + offset] = value;
+                State::Ready
+            }
+            (State::AtmelWrite { remaining }, _, _) => {
+                let address = self.bank * BANK_SIZE + offset;
+                if remaining == ATMEL_PAGE_SIZE {
+                    let page = address & !(ATMEL_PAGE_SIZE - 1);
+                    self.data[page..page + ATMEL_PAGE_SIZE].fill(0xff);
+                }
+                self.data[address] = value;
+                match remaining - 1 {
+                    0 => State::Ready,
+                    remaining => State::AtmelWrite { remaining },
+                }
+            }
+            (State::BankSwitch, 0x0000, _) => {
+                self.bank = usize::from(value & 1);
+                State::Ready
+            }
+            (State::Unlock1, 0x2aaa, 0x55) => State::Unlock2,
+            (State::Unlock2, _, _) => self.command(offset, value),
+            // Some chips leave ID mode without the unlock sequence.
+            (_, 0x5555, 0xf0) => {
+                self.id_mode = false;
+                State::Ready
+            }
+            (_, 0x5555, 0xaa) => State::Unlock1,
+            _ => State::Ready,
+        };
+    }
+
+    fn command(&mut self, offset: usize, value: u8) -> State {
+        let erase_armed = self.erase_armed;
+        self.erase_armed = false;
+
+        match (offset, value) {
+            (0x5555, 0x90) => self.id_mode = true,
+            (0x5555, 0xf0) => self.id_mode = false,
+            (0x5555, 0x80) => self.erase_armed = true,
+            (0x5555, 0x10) if erase_armed => self.data.fill(0xff),
+            (_, 0x30) if erase_armed => {
+                let sector = self.bank * BANK_SIZE + (offset & !(SECTOR_SIZE - 1));
+                self.data[sector..sector + SECTOR_SIZE].fill(0xff);
+            }
+            (0x5555, 0xa0) if self.chip == FlashChip::Atmel => {
+                return State::AtmelWrite {
+                    remaining: ATMEL_PAGE_SIZE,
+                };
+            }
+            (0x5555, 0xa0) => return State::Write,
+            (0x5555, 0xb0) if self.chip.size() > BANK_SIZE => return State::BankSwitch,
+            _ => {}
+        }
+        State::Ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(flash: &mut Flash, value: u8) {
+        flash.write(0x5555, 0xaa);
+        flash.write(0x2aaa, 0x55);
+        flash.write(0x5555, value);
+    }
+
+    #[test]
+    fn id_mode_returns_chip_id() {
+        let mut flash = Flash::new(FlashChip::Sanyo);
+        command(&mut flash, 0x90);
+        assert_eq!([flash.read(0), flash.read(1)], [0x62, 0x13]);
+
+        command(&mut flash, 0xf0);
+        assert_eq!(flash.read(0), 0xff);
+    }
+
+    #[test]
+    fn write_and_erase_sector() {
+        let mut flash = Flash::new(FlashChip::Macronix64K);
+        command(&mut flash, 0xa0);
+        flash.write(0x1234, 0x42);
+        command(&mut flash, 0xa0);
+        flash.write(0x2000, 0x43);
+        // Writes without a command are ignored.
+        flash.write(0x2001, 0x44);
+        assert_eq!(flash.read(0x1234), 0x42);
+        assert_eq!(flash.read(0x2000), 0x43);
+        assert_eq!(flash.read(0x2001), 0xff);
+
+        command(&mut flash, 0x80);
+        flash.write(0x5555, 0xaa);
+        flash.write(0x2aaa, 0x55);
+        flash.write(0x1000, 0x30);
+        assert_eq!(flash.read(0x1234), 0xff);
+        assert_eq!(flash.read(0x2000), 0x43);
+    }
+
+    #[test]
+    fn bank_switching() {
+        let mut flash = Flash::new(FlashChip::Sanyo);
+        command(&mut flash, 0xb0);
+        flash.write(0, 1);
+        command(&mut flash, 0xa0);
+        flash.write(0x10, 0x42);
+
+        assert_eq!(flash.data()[0x10010], 0x42);
+        command(&mut flash, 0xb0);
+        flash.write(0, 0);
+        assert_eq!(flash.read(0x10), 0xff);
+    }
+
+    #[test]
+    fn atmel_writes_whole_pages() {
+        let mut flash = Flash::new(FlashChip::Atmel);
+        command(&mut flash, 0xa0);
+        for i in 0..0x80 {
+            flash.write(0x100 + i, i as u8);
+        }
+        assert_eq!(flash.read(0x17f), 0x7f);
+
+        // Rewriting a page erases the rest of it.
+        command(&mut flash, 0xa0);
+        flash.write(0x100, 0x42);
+        assert_eq!(flash.read(0x100), 0x42);
+        assert_eq!(flash.read(0x17f), 0xff);
+    }
+}
@@ -0,0 +1,245 @@
+mod detect;
+mod eeprom;
+mod flash;
+
+use std::fmt;
+
+pub use self::detect::detect;
+use self::eeprom::Eeprom;
+use self::flash::Flash;
+pub use self::flash::FlashChip;
+
+const SRAM_SIZE: usize = 0x8000;
+
+/// The kind of save memory on the cartridge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackupType {
+    #[default]
+    None,
+    /// 32 KiB of battery backed SRAM.
+    Sram,
+    Flash(FlashChip),
+    /// An EEPROM whose size is worked out from the first request the game makes.
+    Eeprom,
+    Eeprom512B,
+    Eeprom8K,
+}
+
+impl BackupType {
+    /// The size in bytes of the save data. EEPROMs of unknown size are assumed to be the larger
+    /// size.
+    pub fn size(&self) -> usize {
+        match *self {
+            Self::None => 0,
+            Self::Sram => SRAM_SIZE,
+            Self::Flash(chip) => chip.size(),
+            Self::Eeprom512B => 0x200,
+            Self::Eeprom | Self::Eeprom8K => 0x2000,
+        }
+    }
+}
+
+#[derive(Default)]
+pub enum Backup {
+    #[default]
+    None,
+    Sram(Vec<u8>),
+    Flash(Flash),
+    Eeprom(Eeprom),
+}
+
+impl Backup {
+    pub fn new(backup_type: BackupType) -> Self {
+        match backup_type {
+            BackupType::None => Self::None,
+            BackupType::Sram => Self::Sram(vec![0xff; SRAM_SIZE]),
+            BackupType::Flash(chip) => Self::Flash(Flash::new(chip)),
+            BackupType::Eeprom => Self::Eeprom(Eeprom::with_unknown_size()),
+            BackupType::Eeprom512B | BackupType::Eeprom8K => {
+                Self::Eeprom(Eeprom::new(backup_type.size()))
+            }
+        }
+    }
+
+    pub fn backup_type(&self) -> BackupType {
+        match self {
+            Self::None => BackupType::None,
+            Self::Sram(_) => BackupType::Sram,
+            Self::Flash(flash) => BackupType::Flash(flash.chip()),
+            Self::Eeprom(eeprom) if !eeprom.size_known() => BackupType::Eeprom,
+            Self::Eeprom(eeprom) if eeprom.data().len() > 0x200 => BackupType::Eeprom8K,
+            Self::Eeprom(_) => BackupType::Eeprom512B,
+        }
+    }
+
+    pub fn is_eeprom(&self) -> bool {
+        matches!(self, Self::Eeprom(_))
+    }
+
+    /// The raw save data, in the same layout as a .sav file.
+    pub fn data(&self) -> &[u8] {
+        match self {
+            Self::None => &[],
+            Self::Sram(data) => data,
+            Self::Flash(flash) => flash.data(),
+            Self::Eeprom(eeprom) => eeprom.data(),
+        }
+    }
+
+    /// Replace the save data. An empty save leaves the memory erased.
+    pub fn load_data(&mut self, bytes: &[u8]) -> Result<(), BackupError> {
+        // Either EEPROM size is fine if the game hasn't used it yet.
+        if let Self::Eeprom(eeprom) = self {
+            if !eeprom.size_known() && matches!(bytes.len(), 0x200 | 0x2000) {
+                eeprom.set_size(bytes.len());
+            }
+        }
+
+        let data: &mut [u8] = match self {
+            Self::None => &mut [],
+            Self::Sram(data) => data.as_mut_slice(),
+            Self::Flash(flash) => flash.data_mut(),
+            Self::Eeprom(eeprom) => eeprom.data_mut(),
+        };
+
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        if bytes.len() != data.len() {
+            return Err(BackupError::WrongSize {
+                expected: data.len(),
+                actual: bytes.len(),
+            });
+        }
+        data.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Read a byte from the SRAM/Flash region at 0xE000000, which is mirrored every 64 KiB.
+    pub fn read_byte(&self, index: usize) -> u8 {
+        let offset = index & 0xffff;
+        match self {
+            Self::Sram(data) => data[offset & (SRAM_SIZE - 1)],
+            Self::Flash(flash) => flash.read(offset),
+            // Nothing drives the data lines, so they're pulled high.
+            Self::None | Self::Eeprom(_) => 0xff,
+        }
+    }
+
+    pub fn write_byte(&mut self, index: usize, value: u8) {
+        let offset = index & 0xffff;
+        match self {
+            Self::Sram(data) => data[offset & (SRAM_SIZE - 1)] = value,
+            Self::Flash(flash) => flash.write(offset, value),
+            Self::None | Self::Eeprom(_) => {}
+        }
+    }
+
+    /// The bit on the EEPROM's data line, which stays there until `advance_eeprom`.
+    pub fn peek_eeprom(&self) -> u16 {
+        match self {
+            Self::Eeprom(eeprom) => eeprom.peek(),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn advance_eeprom(&self) {
+        if let Self::Eeprom(eeprom) = self {
+            eeprom.advance();
+        }
+    }
+
+    /// Called with the length of every DMA transfer to the EEPROM.
+    pub fn detect_eeprom_size(&mut self, count: u32) {
+        if let Self::Eeprom(eeprom) = self {
+            eeprom.detect_size(count);
+        }
+    }
+
+    pub fn write_eeprom(&mut self, value: u16) {
+        match self {
+            Self::Eeprom(eeprom) => eeprom.write(value),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupError {
+    /// Save files must match the size of the cartridge's save memory.
+    WrongSize { expected: usize, actual: usize },
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongSize { expected, actual } => write!(
+                f,
+                "save must be {} bytes, but the file is {} bytes",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu::Cpu;
+
+    use super::*;
+
+    #[test]
+    fn sram_is_mirrored_with_an_8_bit_bus() {
+        let mut bus = Bus::default();
+        let cpu = Cpu::default();
+        bus.set_backup_override(Some(BackupType::Sram));
+
+        bus.write_byte(0xe000010, 0x12);
+        assert_eq!(bus.read_byte(0xe008010, &cpu), 0x12);
+        assert_eq!(bus.read(0xf010010, &cpu), 0x1212_1212);
+
+        // Only the low byte of a halfword write is stored.
+        bus.write_half(0xe000020, 0x3456);
+        assert_eq!(bus.backup_data()[0x20..0x22], [0x56, 0xff]);
+    }
+
+    #[test]
+    fn eeprom_is_accessed_with_dma3() {
+        let mut bus = Bus::default();
+        let cpu = Cpu::default();
+        bus.set_backup_override(Some(BackupType::Eeprom512B));
+
+        // Write request for block 2, with data 0x8000_0000_0000_0001.
+        let mut bits = vec![1, 0, 0, 0, 0, 0, 1, 0, 1];
+        bits.extend((0..62).map(|_| 0));
+        bits.extend([1, 0]);
+        for (i, &bit) in bits.iter().enumerate() {
+            bus.write_half(0x2000000 + 2 * i as u32, bit);
+        }
+        bus.write(0x40000d4, 0x2000000);
+        bus.write(0x40000d8, 0xd000000);
+        bus.write(0x40000dc, 0x8000_0000 | bits.len() as u32);
+        bus.run_dma(&cpu);
+        assert_eq!(bus.backup_data()[16..24], [0x80, 0, 0, 0, 0, 0, 0, 1]);
+
+        // Read request for block 2.
+        for (i, bit) in [1, 1, 0, 0, 0, 0, 1, 0, 0].into_iter().enumerate() {
+            bus.write_half(0x2000000 + 2 * i as u32, bit);
+        }
+        bus.write(0x40000dc, 0x8000_0009);
+        bus.run_dma(&cpu);
+
+        bus.write(0x40000d4, 0xd000000);
+        bus.write(0x40000d8, 0x2000000);
+        bus.write(0x40000dc, 0x8000_0044);
+        bus.run_dma(&cpu);
+        let read = |i: u32| bus.read_half(0x2000000 + 2 * i, &cpu);
+        assert!((0..4).all(|i| read(i) == 0));
+        assert_eq!(read(4), 1);
+        assert!((5..67).all(|i| read(i) == 0));
+        assert_eq!(read(67), 1);
+    }
+}
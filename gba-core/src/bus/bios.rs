@@ -0,0 +1,88 @@
+use std::fmt;
+
+use crate::utils::get;
+
+pub const BIOS_SIZE: usize = 0x4000;
+
+// Opcodes left in the BIOS read latch at well known points, from the official BIOS.
+pub const LATCH_AFTER_STARTUP: u32 = 0xe129f000;
+pub const LATCH_AFTER_SWI: u32 = 0xe3a02004;
+
+/// Known BIOS images, identified by their checksum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BiosKind {
+    /// The BIOS from a GBA or GBA SP.
+    Official,
+    /// The GBA mode BIOS from a Nintendo DS, which differs from the official BIOS by one byte.
+    NintendoDs,
+    /// The open source replacement BIOS which is embedded in the emulator.
+    CultOfGba,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BiosInfo {
+    /// The value returned by the GetBiosChecksum BIOS call.
+    pub checksum: u32,
+    pub kind: BiosKind,
+}
+
+impl BiosInfo {
+    pub fn identify(bios: &[u8]) -> Self {
+        let checksum = checksum(bios);
+        let kind = match checksum {
+            0xbaae187f => BiosKind::Official,
+            0xbaae1880 => BiosKind::NintendoDs,
+            0x6e83ce82 => BiosKind::CultOfGba,
+            _ => BiosKind::Unknown,
+        };
+        Self { checksum, kind }
+    }
+}
+
+/// The sum of every word in the BIOS, as calculated by GetBiosChecksum.
+pub fn checksum(bios: &[u8]) -> u32 {
+    (0..bios.len() / 4)
+        .map(|i| get::<u32, 4>(bios, 4 * i))
+        .fold(0, u32::wrapping_add)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BiosError {
+    /// BIOS images must be exactly 16 KiB.
+    WrongSize(usize),
+}
+
+impl fmt::Display for BiosError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongSize(size) => write!(
+                f,
+                "BIOS must be {} bytes, but the image is {} bytes",
+                BIOS_SIZE, size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BiosError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifies_embedded_bios() {
+        let info = BiosInfo::identify(include_bytes!("../../cog-bios.bin"));
+        assert_eq!(info.kind, BiosKind::CultOfGba);
+    }
+
+    #[test]
+    fn checksum_sums_words() {
+        let mut bios = vec![0; BIOS_SIZE];
+        bios[0] = 1;
+        bios[4] = 2;
+        bios[BIOS_SIZE - 1] = 0x80;
+        assert_eq!(checksum(&bios), 0x8000_0003);
+    }
+}
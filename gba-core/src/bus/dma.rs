@@ -0,0 +1,307 @@
+use crate::utils::AddressableBits;
+
+use super::Interrupt;
+
+/// The event which starts a DMA transfer, from bits 12-13 of DMAxCNT_H.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaTiming {
+    Immediate,
+    VBlank,
+    HBlank,
+    /// Sound FIFO for DMA1/2 and video capture for DMA3.
+    Special,
+}
+
+impl DmaTiming {
+    fn from_bits(bits: u16) -> Self {
+        match bits {
+            0 => Self::Immediate,
+            1 => Self::VBlank,
+            2 => Self::HBlank,
+            3 => Self::Special,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// How an address changes after each unit is transferred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AddressControl {
+    Increment,
+    Decrement,
+    Fixed,
+    /// Increments during the transfer, then the destination is reloaded on repeat.
+    IncrementReload,
+}
+
+impl AddressControl {
+    fn from_bits(bits: u16) -> Self {
+        match bits {
+            0 => Self::Increment,
+            1 => Self::Decrement,
+            2 => Self::Fixed,
+            3 => Self::IncrementReload,
+            _ => unreachable!(),
+        }
+    }
+
+    fn step(&self, unit_size: u32) -> i32 {
+        let unit_size = unit_size as i32;
+        match *self {
+            Self::Increment | Self::IncrementReload => unit_size,
+            Self::Decrement => -unit_size,
+            Self::Fixed => 0,
+        }
+    }
+}
+
+/// A single transfer, as seen by the bus which has to actually move the data.
+#[derive(Debug, Clone, Copy)]
+pub struct DmaTransfer {
+    pub source: u32,
+    pub destination: u32,
+    pub count: u32,
+    /// True for 32-bit units, false for 16-bit units.
+    pub word: bool,
+    pub source_step: i32,
+    pub destination_step: i32,
+}
+
+#[derive(Debug, Default)]
+struct DmaChannel {
+    // Write-only registers, latched into the internal registers on enable.
+    sad: [u8; 4],
+    dad: [u8; 4],
+    cnt_l: [u8; 2],
+    cnt_h: [u8; 2],
+
+    internal_source: u32,
+    internal_destination: u32,
+    internal_count: u32,
+
+    // Set when the channel's start timing has occurred but the transfer hasn't run yet.
+    pending: bool,
+}
+
+impl DmaChannel {
+    fn control(&self) -> u16 {
+        u16::from_le_bytes(self.cnt_h)
+    }
+
+    fn enabled(&self) -> bool {
+        self.control().bit(15) == 1
+    }
+
+    fn timing(&self) -> DmaTiming {
+        DmaTiming::from_bits(self.control().bits(12, 13))
+    }
+
+    fn repeat(&self) -> bool {
+        self.control().bit(9) == 1
+    }
+
+    fn word(&self) -> bool {
+        self.control().bit(10) == 1
+    }
+
+    fn irq(&self) -> bool {
+        self.control().bit(14) == 1
+    }
+
+    fn destination_control(&self) -> AddressControl {
+        AddressControl::from_bits(self.control().bits(5, 6))
+    }
+
+    fn source_control(&self) -> AddressControl {
+        // Source control 3 is prohibited, treat it as incrementing.
+        AddressControl::from_bits(self.control().bits(7, 8))
+    }
+}
+
+#[derive(Default)]
+pub struct Dma {
+    channels: [DmaChannel; 4],
+}
+
+impl Dma {
+    // DMA0 can only access internal memory, and only DMA3 can write to the game pak.
+    const SOURCE_MASKS: [u32; 4] = [0x07ff_ffff, 0x0fff_ffff, 0x0fff_ffff, 0x0fff_ffff];
+    const DESTINATION_MASKS: [u32; 4] = [0x07ff_ffff, 0x07ff_ffff, 0x07ff_ffff, 0x0fff_ffff];
+    const MAX_COUNTS: [u32; 4] = [0x4000, 0x4000, 0x4000, 0x10000];
+    // Bit 11 (game pak DRQ) only exists on DMA3.
+    const CONTROL_MASKS: [u16; 4] = [0xf7e0, 0xf7e0, 0xf7e0, 0xffe0];
+
+    pub fn interrupt(channel: usize) -> Interrupt {
+        match channel {
+            0 => Interrupt::Dma0,
+            1 => Interrupt::Dma1,
+            2 => Interrupt::Dma2,
+            3 => Interrupt::Dma3,
+            _ => unreachable!(),
+        }
+    }
+
+    fn latch_count(&self, channel: usize) -> u32 {
+        let count = u32::from(u16::from_le_bytes(self.channels[channel].cnt_l));
+        let max = Self::MAX_COUNTS[channel];
+        match count & (max - 1) {
+            0 => max,
+            count => count,
+        }
+    }
+
+    fn enable(&mut self, channel: usize) {
+        let count = self.latch_count(channel);
+        let dma = &mut self.channels[channel];
+        dma.internal_source = u32::from_le_bytes(dma.sad) & Self::SOURCE_MASKS[channel];
+        dma.internal_destination = u32::from_le_bytes(dma.dad) & Self::DESTINATION_MASKS[channel];
+        dma.internal_count = count;
+        dma.pending = dma.timing() == DmaTiming::Immediate;
+    }
+
+    /// Mark every enabled channel waiting on `timing` as ready to transfer.
+    pub fn trigger(&mut self, timing: DmaTiming) {
+        for (channel, dma) in self.channels.iter_mut().enumerate() {
+            // Special timing on DMA0 is prohibited, and DMA1/2 are driven by the sound FIFOs,
+            // which aren't emulated yet.
+            let special_ok = timing != DmaTiming::Special || channel == 3;
+            if dma.enabled() && dma.timing() == timing && special_ok {
+                dma.pending = true;
+            }
+        }
+    }
+
+    /// Disable DMA3 if it is running in video capture mode, which stops at the end of line 161.
+    pub fn stop_video_capture(&mut self) {
+        let dma = &mut self.channels[3];
+        if dma.enabled() && dma.timing() == DmaTiming::Special {
+            dma.cnt_h[1].mut_bit(7, false);
+            dma.pending = false;
+        }
+    }
+
+    /// Returns the highest priority channel which is ready to transfer.
+    pub fn next_pending(&self) -> Option<usize> {
+        self.channels.iter().position(|dma| dma.pending)
+    }
+
+    pub fn start_transfer(&mut self, channel: usize) -> DmaTransfer {
+        let dma = &mut self.channels[channel];
+        dma.pending = false;
+
+        let word = dma.word();
+        let unit_size = if word { 4 } else { 2 };
+
+        DmaTransfer {
+            source: dma.internal_source,
+            destination: dma.internal_destination,
+            count: dma.internal_count,
+            word,
+            source_step: dma.source_control().step(unit_size),
+            destination_step: dma.destination_control().step(unit_size),
+        }
+    }
+
+    /// Store the final addresses of a finished transfer and update the channel for its next
+    /// repeat. Returns true if an interrupt should be raised.
+    pub fn finish_transfer(&mut self, channel: usize, source: u32, destination: u32) -> bool {
+        let count = self.latch_count(channel);
+        let dma = &mut self.channels[channel];
+        dma.internal_source = source & Self::SOURCE_MASKS[channel];
+        dma.internal_destination = destination & Self::DESTINATION_MASKS[channel];
+
+        if dma.repeat() && dma.timing() != DmaTiming::Immediate {
+            dma.internal_count = count;
+            if dma.destination_control() == AddressControl::IncrementReload {
+                dma.internal_destination =
+                    u32::from_le_bytes(dma.dad) & Self::DESTINATION_MASKS[channel];
+            }
+        } else {
+            dma.cnt_h[1].mut_bit(7, false);
+        }
+
+        dma.irq()
+    }
+
+    pub fn read_byte(&self, index: usize) -> u8 {
+        let offset = index - 0x40000b0;
+        let dma = &self.channels[offset / 12];
+        match offset % 12 {
+            // Only the control register can be read back.
+            10..=11 => dma.cnt_h[offset % 12 - 10],
+            _ => 0,
+        }
+    }
+
+    pub fn write_byte(&mut self, index: usize, value: u8) {
+        let offset = index - 0x40000b0;
+        let channel = offset / 12;
+        let dma = &mut self.channels[channel];
+        match offset % 12 {
+            0..=3 => dma.sad[offset % 12] = value,
+            4..=7 => dma.dad[offset % 12 - 4] = value,
+            8..=9 => dma.cnt_l[offset % 12 - 8] = value,
+            10 => dma.cnt_h[0] = value & Self::CONTROL_MASKS[channel] as u8,
+            11 => {
+                let was_enabled = dma.enabled();
+                dma.cnt_h[1] = value & (Self::CONTROL_MASKS[channel] >> 8) as u8;
+                if !dma.enabled() {
+                    dma.pending = false;
+                } else if !was_enabled {
+                    self.enable(channel);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu::Cpu;
+
+    use super::*;
+
+    #[test]
+    fn immediate_word_transfer() {
+        let mut bus = Bus::default();
+        let cpu = Cpu::default();
+
+        for i in 0..4 {
+            bus.write(0x2000000 + 4 * i, 0x11111111 * (i + 1));
+        }
+        bus.write(0x40000d4, 0x2000000);
+        bus.write(0x40000d8, 0x3000100);
+        bus.write(0x40000dc, 0x8400_0004);
+        bus.run_dma(&cpu);
+
+        for i in 0..4 {
+            assert_eq!(bus.read(0x3000100 + 4 * i, &cpu), 0x11111111 * (i + 1));
+        }
+        // Non-repeating transfers disable themselves.
+        assert_eq!(bus.read_half(0x40000de, &cpu), 0x0400);
+    }
+
+    #[test]
+    fn vblank_transfer_waits_for_trigger_and_raises_irq() {
+        let mut bus = Bus::default();
+        let cpu = Cpu::default();
+
+        bus.write_half(0x2000000, 0xabcd);
+        bus.write(0x40000b0, 0x2000000);
+        bus.write(0x40000b4, 0x3000000);
+        // Halfword units, fixed source, VBlank timing, repeat and IRQ enabled.
+        bus.write(0x40000b8, 0xd300_0002);
+        bus.run_dma(&cpu);
+        assert_eq!(bus.read_half(0x3000000, &cpu), 0);
+
+        bus.io_map.dma.trigger(DmaTiming::VBlank);
+        bus.run_dma(&cpu);
+        assert_eq!(bus.read_half(0x3000000, &cpu), 0xabcd);
+        assert_eq!(bus.read_half(0x3000002, &cpu), 0xabcd);
+        assert_eq!(bus.read_half(0x4000202, &cpu), 1 << 8);
+        // Repeating transfers stay enabled.
+        assert_eq!(bus.read_half(0x40000ba, &cpu).bit(15), 1);
+    }
+}
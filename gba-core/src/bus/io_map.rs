@@ -1,21 +1,79 @@
 use num_traits::{FromBytes, ToBytes};
 use wasm_bindgen::prelude::wasm_bindgen;
 
+use crate::scheduler::{Event, Scheduler};
 use crate::utils::AddressableBits;
 
+use super::dma::{Dma, DmaTiming};
+use super::io_regs::{self, IoRegs};
+use super::timers::Timers;
+use super::waitcnt::WaitControl;
+
+/// The 14 interrupt sources, one for each bit of IE and IF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Interrupt {
     VBlank,
     HBlank,
     VCount,
+    Timer0,
+    Timer1,
+    Timer2,
+    Timer3,
+    Serial,
+    Dma0,
+    Dma1,
+    Dma2,
+    Dma3,
+    Keypad,
+    // Raised when the cartridge is removed, which can't happen while emulating.
+    GamePak,
+}
+
+impl Interrupt {
+    pub fn bit(&self) -> usize {
+        match *self {
+            Self::VBlank => 0,
+            Self::HBlank => 1,
+            Self::VCount => 2,
+            Self::Timer0 => 3,
+            Self::Timer1 => 4,
+            Self::Timer2 => 5,
+            Self::Timer3 => 6,
+            Self::Serial => 7,
+            Self::Dma0 => 8,
+            Self::Dma1 => 9,
+            Self::Dma2 => 10,
+            Self::Dma3 => 11,
+            Self::Keypad => 12,
+            Self::GamePak => 13,
+        }
+    }
+}
+
+/// Low-power states entered by writing to HALTCNT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerState {
+    Running,
+    /// The CPU is paused until an enabled interrupt is requested.
+    Halted,
+    /// Like halt, but the PPU and timers are paused too.
+    Stopped,
 }
 
 pub struct IoMap {
-    mock: [u8; 0x400],
+    regs: IoRegs,
+    pub(crate) dma: Dma,
+    pub(crate) timers: Timers,
+    pub(crate) waitcnt: WaitControl,
+    pub(crate) scheduler: Scheduler,
     keyinput: u16,
-    ime: [u8; 4],
+    keycnt: u16,
     ie: [u8; 2],
     // Normally called 'IF', but 'if' is a keyword.
     pub irq_flags: [u8; 2],
+    // Whether the CPU is being sent an IRQ, updated by `Event::Irq`.
+    irq_line: bool,
+    power_state: PowerState,
 }
 
 #[cfg_attr(feature="debugger", wasm_bindgen)]
@@ -50,34 +108,143 @@ impl Key {
 }
 
 const BASE_ADDR: usize = 0x4000000;
+/// The bytes of IE and IF which belong to an interrupt source.
+const IRQ_MASK: [u8; 2] = [0xff, 0x3f];
 
 impl IoMap {
     pub fn new() -> Self {
         Self {
-            mock: [0; 0x400],
+            regs: IoRegs::default(),
+            dma: Dma::default(),
+            timers: Timers::default(),
+            waitcnt: WaitControl::default(),
+            scheduler: Scheduler::default(),
             keyinput: 0x3ff,
-            ime: [0; 4],
+            keycnt: 0,
             ie: [0; 2],
             irq_flags: [0; 2],
+            irq_line: false,
+            power_state: PowerState::Running,
         }
     }
 
+    /// Whether an IO address doesn't belong to any register, in which case reads return open
+    /// bus.
+    pub fn is_unused(index: usize) -> bool {
+        matches!(
+            index - BASE_ADDR,
+            0x056..=0x05f
+                | 0x08a..=0x08f
+                | 0x0a8..=0x0af
+                | 0x0e0..=0x0ff
+                | 0x110..=0x11f
+                | 0x12c..=0x12f
+                | 0x136..=0x13f
+                | 0x142..=0x14f
+                | 0x15a..=0x1ff
+                | 0x206..=0x207
+                | 0x20a..=0x2ff
+                | 0x302..=0x3ff
+        )
+    }
+
     pub fn set_interrupt(&mut self, interrupt: Interrupt, value: bool) {
-        let bit: usize = match interrupt {
-            Interrupt::VBlank => 0,
-            Interrupt::HBlank => 1,
-            Interrupt::VCount => 2,
-        };
+        let bit = interrupt.bit();
 
         if bit < 8 {
             self.irq_flags[0].mut_bit(bit, value);
         } else {
             self.irq_flags[1].mut_bit(bit - 8, value);
         }
+        self.update_irq();
+    }
+
+    pub fn power_state(&self) -> PowerState {
+        self.power_state
+    }
+
+    /// Whether IME is set and an enabled interrupt is requested. The CPU still has to check its
+    /// own I flag.
+    pub fn irq_line(&self) -> bool {
+        self.irq_line
+    }
+
+    fn ime(&self) -> bool {
+        self.regs.get(io_regs::IME).read() == 1
+    }
+
+    fn irq_requested(&self) -> bool {
+        u16::from_le_bytes(self.ie) & u16::from_le_bytes(self.irq_flags) != 0
+    }
+
+    /// Called whenever IE, IF, IME or HALTCNT change. A newly requested interrupt reaches the CPU
+    /// through an `Event::Irq`, but the line drops straight away once nothing is requested.
+    fn update_irq(&mut self) {
+        if self.irq_requested() {
+            self.scheduler.schedule(Event::Irq, 0);
+        }
+        if !self.irq_requested() || !self.ime() {
+            self.irq_line = false;
+        }
+    }
+
+    /// Handle `Event::Irq`. Any interrupt which is both enabled and requested wakes the CPU from
+    /// halt or stop, even if IME is clear.
+    pub fn handle_irq_event(&mut self) {
+        if self.irq_requested() {
+            self.power_state = PowerState::Running;
+        }
+        self.irq_line = self.irq_requested() && self.ime();
+    }
+
+    /// Handle a timer overflow event, raising an interrupt for each timer that overflowed with
+    /// IRQs enabled.
+    pub fn handle_timer_overflow(&mut self, timer: usize, time: u64) {
+        let irqs = self.timers.overflow(timer, time, &mut self.scheduler);
+        for (timer, irq) in irqs.into_iter().enumerate() {
+            if irq {
+                self.set_interrupt(Timers::interrupt(timer), true);
+            }
+        }
+    }
+
+    /// Start any DMA channels waiting on `timing`.
+    pub fn trigger_dma(&mut self, timing: DmaTiming) {
+        self.dma.trigger(timing);
+        self.schedule_dma();
+    }
+
+    /// Queue an `Event::Dma` if any channel is ready to transfer.
+    fn schedule_dma(&mut self) {
+        if self.dma.next_pending().is_some() {
+            self.scheduler.schedule(Event::Dma, 0);
+        }
     }
 
     pub fn set_key(&mut self, key: Key, pressed: bool) {
         self.keyinput.mut_bit(key.bit(), !pressed);
+        self.update_keypad_irq();
+    }
+
+    /// Raise a keypad interrupt if KEYCNT has IRQs enabled and its condition is met. Bit 15
+    /// selects between any (0) or all (1) of the selected keys being pressed.
+    fn update_keypad_irq(&mut self) {
+        if self.keycnt.bit(14) == 0 {
+            return;
+        }
+
+        let selected = self.keycnt.bits(0, 9);
+        // KEYINPUT bits are 0 when pressed.
+        let pressed = !self.keyinput & 0x3ff;
+        let condition = if self.keycnt.bit(15) == 1 {
+            selected != 0 && pressed & selected == selected
+        } else {
+            pressed & selected != 0
+        };
+
+        if condition {
+            self.set_interrupt(Interrupt::Keypad, true);
+        }
     }
 
     pub fn read<T, const N: usize>(&self, index: usize) -> T
@@ -99,15 +266,16 @@ impl IoMap {
             0..=0x3ffffff => {
                 unreachable!()
             }
+            0x40000b0..=0x40000df => self.dma.read_byte(index),
+            0x4000100..=0x400010f => self.timers.read_byte(index, self.scheduler.now()),
             0x4000130 => self.keyinput as u8,
             0x4000131 => (self.keyinput >> 8) as u8,
+            0x4000132 => self.keycnt as u8,
+            0x4000133 => (self.keycnt >> 8) as u8,
             0x4000200..=0x4000201 => self.ie[index - 0x4000200],
             0x4000202..=0x4000203 => self.irq_flags[index - 0x4000202],
-            0x4000208..=0x400020b => self.ime[index - 0x4000208],
-            0x4000000..=0x40003ff => {
-                let index = index - BASE_ADDR;
-                self.mock[index]
-            }
+            0x4000204..=0x4000205 => self.waitcnt.read_byte(index),
+            0x4000000..=0x40003ff => self.regs.read_byte(index),
             _ => {
                 unreachable!()
             }
@@ -121,6 +289,31 @@ impl IoMap {
         for (i, b) in value.to_le_bytes().into_iter().enumerate() {
             self.write_byte(index + i, b);
         }
+
+        // Wait until the whole access is written so the start and IRQ bits are seen together.
+        if (index..index + N).any(|i| i == 0x4000128 || i == 0x4000129) {
+            self.update_serial();
+        }
+    }
+
+    /// Complete a normal mode transfer started with the internal clock. There's never anything
+    /// connected to the link port, so the transfer finishes immediately and reads all ones.
+    fn update_serial(&mut self) {
+        let siocnt = self.regs.get(io_regs::SIOCNT).value();
+        let rcnt = self.regs.get(io_regs::RCNT).value();
+
+        let normal_mode = siocnt.bit(13) == 0 && rcnt.bit(15) == 0;
+        let internal_clock = siocnt.bit(0) == 1;
+        if normal_mode && internal_clock && siocnt.bit(7) == 1 {
+            self.regs.get_mut(io_regs::SIODATA32_L).set(0xffff);
+            self.regs.get_mut(io_regs::SIODATA32_H).set(0xffff);
+            let data8 = self.regs.get(io_regs::SIODATA8).value();
+            self.regs.get_mut(io_regs::SIODATA8).set(data8 | 0xff);
+            self.regs.get_mut(io_regs::SIOCNT).set(siocnt.set_bit(7, false));
+            if siocnt.bit(14) == 1 {
+                self.set_interrupt(Interrupt::Serial, true);
+            }
+        }
     }
 
     fn write_byte(&mut self, index: usize, value: u8) {
@@ -131,18 +324,99 @@ impl IoMap {
             0..=0x3ffffff => {
                 unreachable!()
             }
-            0x4000130 => self.keyinput = (self.keyinput & 0xff00) | value as u16,
-            0x4000131 => self.keyinput = (self.keyinput & 0xff) | value as u16,
-            0x4000200..=0x4000201 => self.ie[index - 0x4000200] = value,
-            0x4000202..=0x4000203 => self.irq_flags[index - 0x4000202] &= !value,
-            0x4000208..=0x400020b => self.ime[index - 0x4000208] = value,
-            0x4000000..=0x40003ff => {
-                let index = index - BASE_ADDR;
-                self.mock[index] = value;
+            0x40000b0..=0x40000df => {
+                self.dma.write_byte(index, value);
+                self.schedule_dma();
+            }
+            0x4000100..=0x400010f => self.timers.write_byte(index, value, &mut self.scheduler),
+            // KEYINPUT is read-only.
+            0x4000130..=0x4000131 => {}
+            0x4000132 => {
+                self.keycnt = (self.keycnt & 0xff00) | value as u16;
+                self.update_keypad_irq();
+            }
+            0x4000133 => {
+                self.keycnt = (self.keycnt & 0xff) | ((value as u16 & 0xc3) << 8);
+                self.update_keypad_irq();
             }
+            // IE and IF only have 14 bits.
+            0x4000200..=0x4000201 => {
+                self.ie[index - 0x4000200] = value & IRQ_MASK[index & 1];
+                self.update_irq();
+            }
+            0x4000202..=0x4000203 => {
+                self.irq_flags[index - 0x4000202] &= !value;
+                self.update_irq();
+            }
+            0x4000204..=0x4000205 => self.waitcnt.write_byte(index, value),
+            0x4000208..=0x4000209 => {
+                self.regs.write_byte(index, value);
+                self.update_irq();
+            }
+            // HALTCNT
+            0x4000301 => {
+                self.power_state = if value.bit(7) == 0 {
+                    PowerState::Halted
+                } else {
+                    PowerState::Stopped
+                };
+                // An interrupt which is already requested wakes the CPU straight away.
+                self.update_irq();
+            }
+            0x4000000..=0x40003ff => self.regs.write_byte(index, value),
             _ => {
                 unreachable!()
             }
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keypad_irq(io_map: &IoMap) -> bool {
+        io_map.read::<u16, 2>(0x4000202).bit(Interrupt::Keypad.bit()) == 1
+    }
+
+    #[test]
+    fn keypad_irq_or_condition() {
+        let mut io_map = IoMap::new();
+        // IRQ when A or B is pressed.
+        io_map.write::<u16, 2>(0x4000132, 0x4003);
+
+        io_map.set_key(Key::Start, true);
+        assert!(!keypad_irq(&io_map));
+        io_map.set_key(Key::B, true);
+        assert!(keypad_irq(&io_map));
+    }
+
+    #[test]
+    fn keypad_irq_and_condition() {
+        let mut io_map = IoMap::new();
+        // IRQ when A, B, Select and Start are all pressed.
+        io_map.write::<u16, 2>(0x4000132, 0xc00f);
+
+        io_map.set_key(Key::A, true);
+        io_map.set_key(Key::B, true);
+        io_map.set_key(Key::Select, true);
+        assert!(!keypad_irq(&io_map));
+        io_map.set_key(Key::Start, true);
+        assert!(keypad_irq(&io_map));
+    }
+
+    #[test]
+    fn masked_registers() {
+        let mut io_map = IoMap::new();
+        io_map.write::<u16, 2>(0x4000130, 0);
+        assert_eq!(io_map.read::<u16, 2>(0x4000130), 0x3ff);
+
+        io_map.write::<u16, 2>(0x4000200, 0xffff);
+        assert_eq!(io_map.read::<u16, 2>(0x4000200), 0x3fff);
+
+        // HALTCNT is write-only, next to POSTFLG.
+        io_map.write::<u16, 2>(0x4000300, 0x0001);
+        assert_eq!(io_map.read::<u16, 2>(0x4000300), 0x0001);
+        assert_eq!(io_map.power_state(), PowerState::Halted);
+    }
+}
@@ -0,0 +1,184 @@
+/// SOUNDBIAS starts at the middle of its range, so silence is centred.
+const SOUNDBIAS_DEFAULT: u16 = 0x200;
+
+pub const SIODATA32_L: usize = 0x120;
+pub const SIODATA32_H: usize = 0x122;
+pub const SIOCNT: usize = 0x128;
+pub const SIODATA8: usize = 0x12a;
+pub const RCNT: usize = 0x134;
+pub const IME: usize = 0x208;
+const SOUNDBIAS: usize = 0x088;
+
+/// Registers which don't need their own module, as (offset, read mask, write mask). Halfwords
+/// which aren't listed, like the upper half of SOUND1CNT_X, read as 0 and ignore writes.
+const REGISTERS: [(usize, u16, u16); 41] = [
+    // Sound channel 1. Lengths and frequencies are write-only.
+    (0x060, 0x007f, 0x007f),
+    (0x062, 0xffc0, 0xffff),
+    (0x064, 0x4000, 0xc7ff),
+    // Sound channel 2.
+    (0x068, 0xffc0, 0xffff),
+    (0x06c, 0x4000, 0xc7ff),
+    // Sound channel 3.
+    (0x070, 0x00e0, 0x00e0),
+    (0x072, 0xe000, 0xe0ff),
+    (0x074, 0x4000, 0xc7ff),
+    // Sound channel 4.
+    (0x078, 0xff00, 0xff3f),
+    (0x07c, 0x40ff, 0xc0ff),
+    // SOUNDCNT_L, SOUNDCNT_H and SOUNDCNT_X. The FIFO resets are write-only and the channel
+    // status flags are read-only.
+    (0x080, 0xff77, 0xff77),
+    (0x082, 0x770f, 0xff0f),
+    (0x084, 0x008f, 0x0080),
+    (SOUNDBIAS, 0xc3fe, 0xc3fe),
+    // Wave RAM.
+    (0x090, 0xffff, 0xffff),
+    (0x092, 0xffff, 0xffff),
+    (0x094, 0xffff, 0xffff),
+    (0x096, 0xffff, 0xffff),
+    (0x098, 0xffff, 0xffff),
+    (0x09a, 0xffff, 0xffff),
+    (0x09c, 0xffff, 0xffff),
+    (0x09e, 0xffff, 0xffff),
+    // FIFO A and B can only be written.
+    (0x0a0, 0x0000, 0xffff),
+    (0x0a2, 0x0000, 0xffff),
+    (0x0a4, 0x0000, 0xffff),
+    (0x0a6, 0x0000, 0xffff),
+    // SIOMULTI0-3, which share their first two halfwords with SIODATA32.
+    (SIODATA32_L, 0xffff, 0xffff),
+    (SIODATA32_H, 0xffff, 0xffff),
+    (0x124, 0xffff, 0xffff),
+    (0x126, 0xffff, 0xffff),
+    (SIOCNT, 0x7fff, 0x7fff),
+    (SIODATA8, 0xffff, 0xffff),
+    (RCNT, 0xc1ff, 0xc1ff),
+    // JOYCNT. The JOY bus is never used, so its reset and transfer flags are never set.
+    (0x140, 0x0047, 0x0040),
+    // JOY_RECV and JOY_TRANS.
+    (0x150, 0xffff, 0xffff),
+    (0x152, 0xffff, 0xffff),
+    (0x154, 0xffff, 0xffff),
+    (0x156, 0xffff, 0xffff),
+    // JOYSTAT, whose transfer flags are read-only.
+    (0x158, 0x003a, 0x0030),
+    // IME only has one bit.
+    (IME, 0x0001, 0x0001),
+    // POSTFLG. HALTCNT in the upper byte is write-only and handled by `IoMap`.
+    (0x300, 0x0001, 0x0001),
+];
+
+/// A 16 bit IO register where some bits may be read-only, write-only or unused.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoReg {
+    value: u16,
+    read_mask: u16,
+    write_mask: u16,
+}
+
+impl IoReg {
+    const fn new(read_mask: u16, write_mask: u16) -> Self {
+        Self {
+            value: 0,
+            read_mask,
+            write_mask,
+        }
+    }
+
+    /// The value seen by the CPU, where write-only and unused bits read as 0.
+    pub fn read(&self) -> u16 {
+        self.value & self.read_mask
+    }
+
+    /// Write from the CPU, which leaves read-only bits alone.
+    pub fn write(&mut self, value: u16) {
+        self.value = (self.value & !self.write_mask) | (value & self.write_mask);
+    }
+
+    /// The stored value, including write-only bits.
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+
+    /// Update the register from the hardware side, ignoring the write mask.
+    pub fn set(&mut self, value: u16) {
+        self.value = value;
+    }
+}
+
+/// Every IO register from 0x4000060 which is only stored, indexed by halfword.
+pub struct IoRegs {
+    regs: [IoReg; 0x200],
+}
+
+impl Default for IoRegs {
+    fn default() -> Self {
+        let mut regs = [IoReg::default(); 0x200];
+        for (offset, read_mask, write_mask) in REGISTERS {
+            regs[offset / 2] = IoReg::new(read_mask, write_mask);
+        }
+        regs[SOUNDBIAS / 2].set(SOUNDBIAS_DEFAULT);
+        Self { regs }
+    }
+}
+
+impl IoRegs {
+    /// Get a register by its offset from 0x4000000.
+    pub fn get(&self, offset: usize) -> &IoReg {
+        &self.regs[offset / 2]
+    }
+
+    pub fn get_mut(&mut self, offset: usize) -> &mut IoReg {
+        &mut self.regs[offset / 2]
+    }
+
+    pub fn read_byte(&self, index: usize) -> u8 {
+        let offset = index & 0x3ff;
+        self.get(offset).read().to_le_bytes()[offset & 1]
+    }
+
+    pub fn write_byte(&mut self, index: usize, value: u8) {
+        let offset = index & 0x3ff;
+        let reg = self.get_mut(offset);
+        let mut bytes = reg.value().to_le_bytes();
+        bytes[offset & 1] = value;
+        reg.write(u16::from_le_bytes(bytes));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_only_bits_read_as_zero() {
+        let mut regs = IoRegs::default();
+        // SOUND1CNT_X frequency and restart.
+        regs.write_byte(0x4000064, 0xff);
+        regs.write_byte(0x4000065, 0xff);
+        assert_eq!(regs.get(0x064).read(), 0x4000);
+        assert_eq!(regs.get(0x064).value(), 0xc7ff);
+
+        regs.write_byte(0x40000a0, 0x12);
+        assert_eq!(regs.read_byte(0x40000a0), 0);
+    }
+
+    #[test]
+    fn read_only_bits_ignore_writes() {
+        let mut regs = IoRegs::default();
+        regs.write_byte(0x4000084, 0xff);
+        assert_eq!(regs.read_byte(0x4000084), 0x80);
+
+        regs.write_byte(0x4000208, 0xff);
+        assert_eq!(regs.read_byte(0x4000208), 1);
+        regs.write_byte(0x4000066, 0xff);
+        assert_eq!(regs.read_byte(0x4000066), 0);
+    }
+
+    #[test]
+    fn soundbias_starts_centred() {
+        let regs = IoRegs::default();
+        assert_eq!(regs.get(0x088).read(), 0x200);
+    }
+}
@@ -1,14 +1,33 @@
+mod backup;
+mod bios;
+mod dma;
 mod io_map;
+mod io_regs;
+mod prefetch;
+mod rom_header;
+mod timers;
+mod waitcnt;
 
+pub use backup::{BackupError, BackupType, FlashChip};
+pub use bios::{BiosError, BiosInfo, BiosKind};
+pub(crate) use bios::LATCH_AFTER_SWI;
+pub use dma::DmaTiming;
 pub use io_map::Key;
-pub use io_map::{Interrupt, IoMap};
+pub use io_map::{Interrupt, IoMap, PowerState};
+pub use rom_header::RomHeader;
+use std::cell::Cell;
+
 use num_traits::{AsPrimitive, FromBytes, ToBytes, Zero};
 use wasm_bindgen::prelude::wasm_bindgen;
 
+use self::backup::Backup;
+use self::prefetch::Prefetch;
+use self::waitcnt::Width;
 use crate::{
-    cpu::Cpu,
+    cpu::{Cpu, State},
     ppu::Ppu,
     utils::{get, set, AddressableBits},
+    Error,
 };
 
 #[cfg_attr(feature="debugger", wasm_bindgen)]
@@ -24,12 +43,28 @@ impl MemoryDetails {
     }
 }
 
+/// The game pak address space is 32 MiB, mirrored for each set of wait states.
+const MAX_ROM_SIZE: usize = 0x2000000;
+
 pub struct Bus {
     bios: Vec<u8>,//[u8; 0x4000],
+    // The last opcode fetched from the BIOS, which is what BIOS reads return when the CPU isn't
+    // executing inside it.
+    pub(crate) bios_latch: u32,
     ew_ram: Vec<u8>,//[u8; 0x40000],
     iw_ram: Vec<u8>,//[u8; 0x8000],
 
+    // Only as large as the loaded ROM.
     game_pak_rom: Vec<u8>,
+    rom_header: Option<RomHeader>,
+    backup: Backup,
+    backup_override: Option<BackupType>,
+
+    // The address following the last access, which the next access is sequential with.
+    next_sequential: Cell<u32>,
+    // Cycles taken by accesses since the CPU last collected them.
+    access_cycles: Cell<u32>,
+    prefetch: Cell<Prefetch>,
 
     pub(crate) io_map: IoMap,
 
@@ -38,22 +73,125 @@ pub struct Bus {
 
 impl Default for Bus {
     fn default() -> Self {
+        let mut io_map = IoMap::new();
+        Ppu::schedule_first_line(&mut io_map.scheduler);
+
         Self {
             bios: include_bytes!("../../cog-bios.bin").to_vec(),
+            bios_latch: 0,
             ew_ram: vec![0; 0x40000],
             iw_ram: vec![0; 0x8000],
 
-            game_pak_rom: vec![0; 0x2000000],
+            game_pak_rom: Vec::new(),
+            rom_header: None,
+            backup: Backup::default(),
+            backup_override: None,
+
+            next_sequential: Cell::new(0),
+            access_cycles: Cell::new(0),
+            prefetch: Cell::new(Prefetch::default()),
 
             ppu: Ppu::default(),
-            io_map: IoMap::new(),
+            io_map,
         }
     }
 }
 
 impl Bus {
-    pub fn set_bios(&mut self, bios: &[u8]) {
+    pub fn set_bios(&mut self, bios: &[u8]) -> Result<(), BiosError> {
+        if bios.len() != bios::BIOS_SIZE {
+            return Err(BiosError::WrongSize(bios.len()));
+        }
         self.bios.clone_from_slice(bios);
+        Ok(())
+    }
+
+    pub fn bios(&self) -> &[u8] {
+        &self.bios
+    }
+
+    pub fn bios_info(&self) -> BiosInfo {
+        BiosInfo::identify(&self.bios)
+    }
+
+    /// Put the BIOS latch in the state the BIOS leaves it in after booting.
+    pub fn skip_bios(&mut self) {
+        self.bios_latch = bios::LATCH_AFTER_STARTUP;
+    }
+
+    /// Fetch an opcode for the CPU, latching it if it comes from the BIOS.
+    pub fn fetch(&mut self, address: u32, cpu: &Cpu) -> u32 {
+        if (address as usize) < bios::BIOS_SIZE {
+            self.bios_latch = get(&self.bios, address as usize & 0x3ffc);
+        }
+        match cpu.get_state() {
+            State::ARM => {
+                self.add_fetch_cycles(address & !3, Width::Word);
+                self.peek(address, cpu)
+            }
+            State::Thumb => {
+                self.add_fetch_cycles(address & !1, Width::Half);
+                self.peek_half(address, cpu)
+            }
+        }
+    }
+
+    /// Add the cost of an opcode fetch, which can come from the prefetch buffer when it's
+    /// enabled and the game pak is being executed from.
+    fn add_fetch_cycles(&self, address: u32, width: Width) {
+        let waitcnt = &self.io_map.waitcnt;
+        if !waitcnt.prefetch() || !(0x08..=0x0d).contains(&(address >> 24)) {
+            self.add_access_cycles(address, width);
+            return;
+        }
+
+        let mut prefetch = self.prefetch.get();
+        match prefetch.fetch(address, width, waitcnt) {
+            Some(cycles) => {
+                self.access_cycles.set(self.access_cycles.get() + cycles);
+                self.next_sequential.set(address.wrapping_add(width.bytes()));
+            }
+            None => {
+                self.add_access_cycles(address, width);
+                prefetch.restart(address.wrapping_add(width.bytes()));
+            }
+        }
+        self.prefetch.set(prefetch);
+    }
+
+    /// Spend cycles without using the bus, which lets the prefetch buffer read ahead.
+    pub fn idle(&self, cycles: u32) {
+        self.access_cycles.set(self.access_cycles.get() + cycles);
+        let mut prefetch = self.prefetch.get();
+        prefetch.idle(cycles, &self.io_map.waitcnt);
+        self.prefetch.set(prefetch);
+    }
+
+    /// Add the cost of an access to the cycles owed by the CPU.
+    fn add_access_cycles(&self, address: u32, width: Width) {
+        // Sequential accesses can't cross a 128 KiB boundary, where the game pak needs a new
+        // address.
+        let sequential = address == self.next_sequential.get() && address & 0x1ffff != 0;
+        let cycles = self
+            .io_map
+            .waitcnt
+            .access_cycles(address, width, sequential);
+        self.access_cycles.set(self.access_cycles.get() + cycles);
+        self.next_sequential.set(address.wrapping_add(width.bytes()));
+
+        // The prefetch buffer can only read while the game pak bus is free.
+        let mut prefetch = self.prefetch.get();
+        if (0x08..=0x0f).contains(&(address >> 24)) {
+            prefetch.stop();
+        } else {
+            prefetch.idle(cycles, &self.io_map.waitcnt);
+        }
+        self.prefetch.set(prefetch);
+    }
+
+    /// Return the number of cycles taken by bus accesses since the last call.
+    pub fn take_access_cycles(&self) -> u32 {
+        self.access_cycles.replace(0)
     }
 
     pub fn set_key(&mut self, key: Key, pressed: bool) {
@@ -66,8 +204,80 @@ impl Bus {
         }
     }
 
-    pub fn load_rom(&mut self, bytes: &[u8]) {
-        self.game_pak_rom[..bytes.len()].clone_from_slice(bytes);
+    pub fn load_rom(&mut self, bytes: &[u8]) -> crate::Result<()> {
+        if bytes.is_empty() {
+            return Err(Error::EmptyRom);
+        }
+        if bytes.len() > MAX_ROM_SIZE {
+            return Err(Error::RomTooLarge(bytes.len()));
+        }
+
+        self.game_pak_rom = bytes.to_vec();
+        self.rom_header = RomHeader::parse(bytes);
+        self.backup = Backup::new(self.backup_override.unwrap_or_else(|| backup::detect(bytes)));
+        Ok(())
+    }
+
+    /// The header of the loaded ROM, if it was large enough to have one.
+    pub fn rom_header(&self) -> Option<&RomHeader> {
+        self.rom_header.as_ref()
+    }
+
+    /// Use the given type of save memory instead of detecting it from the ROM, or go back to
+    /// detection with `None`. The save memory is replaced with an erased chip.
+    pub fn set_backup_override(&mut self, backup_type: Option<BackupType>) {
+        self.backup_override = backup_type;
+        let rom = &self.game_pak_rom;
+        self.backup = Backup::new(backup_type.unwrap_or_else(|| backup::detect(rom)));
+    }
+
+    pub fn backup_override(&self) -> Option<BackupType> {
+        self.backup_override
+    }
+
+    pub fn backup_type(&self) -> BackupType {
+        self.backup.backup_type()
+    }
+
+    pub fn backup_data(&self) -> &[u8] {
+        self.backup.data()
+    }
+
+    pub fn load_backup_data(&mut self, bytes: &[u8]) -> Result<(), BackupError> {
+        self.backup.load_data(bytes)
+    }
+
+    fn is_eeprom_address(&self, index: usize) -> bool {
+        // Cartridges larger than 16 MiB only leave the top of the 0xD region for the EEPROM.
+        self.backup.is_eeprom() && (self.game_pak_rom.len() <= 0x1000000 || index >= 0xdffff00)
+    }
+
+    /// Run every DMA transfer which is ready, in channel priority order.
+    pub fn run_dma(&mut self, cpu: &Cpu) {
+        while let Some(channel) = self.io_map.dma.next_pending() {
+            let transfer = self.io_map.dma.start_transfer(channel);
+            if self.is_eeprom_address(transfer.destination as usize) {
+                self.backup.detect_eeprom_size(transfer.count);
+            }
+
+            let mut source = transfer.source;
+            let mut destination = transfer.destination;
+            for _ in 0..transfer.count {
+                if transfer.word {
+                    let value = self.read(source & !3, cpu);
+                    self.write(destination & !3, value);
+                } else {
+                    let value = self.read_half(source & !1, cpu) as u16;
+                    self.write_half(destination & !1, value);
+                }
+                source = source.wrapping_add_signed(transfer.source_step);
+                destination = destination.wrapping_add_signed(transfer.destination_step);
+            }
+
+            if self.io_map.dma.finish_transfer(channel, source, destination) {
+                self.io_map.set_interrupt(dma::Dma::interrupt(channel), true);
+            }
+        }
     }
 
     fn read_internal<T, const N: usize>(&self, address: u32, cpu: &Cpu) -> T
@@ -79,49 +289,107 @@ impl Bus {
         assert_eq!(address % u32::try_from(N).unwrap(), 0);
         let index: usize = address.try_into().unwrap();
         match index {
-            0x0000000..=0x1ffffff => get(&self.bios, index & 0x3ffc),
+            0x0000000..=0x0003fff if cpu.executing_bios() => get(&self.bios, index),
+            0x0000000..=0x0003fff => get(&self.bios_latch.to_le_bytes(), index & 3),
             0x2000000..=0x2ffffff => get(&self.ew_ram, index & 0x3ffff),
             0x3000000..=0x3ffffff => get(&self.iw_ram, index & 0x7fff),
-            0x4000000..=0x4ffffff => match index & 0x3ff {
-                0..=0x5f => self.ppu.read_lcd_io_regs::<T, N>(index & 0x40003ff),
-                0x60..=0x3fe => self.io_map.read(index & 0x40003ff),
-                0x3ff => todo!(),
-                _ => unreachable!(),
-            },
+            0x4000000..=0x40003ff if IoMap::is_unused(index) => self.open_bus(index, cpu),
+            0x4000000..=0x400005f if Ppu::is_write_only(index) => self.open_bus(index, cpu),
+            0x4000000..=0x400005f => self.ppu.read_lcd_io_regs::<T, N>(index),
+            0x4000060..=0x40003ff => self.io_map.read(index),
             0x5000000..=0x7ffffff => self.ppu.read_simple::<T, N>(index),
-            0x8000000..=0x9ffffff => get(&self.game_pak_rom, index - 0x8000000),
-            // There's some timing stuff about these mirrored game pak sections but I'm ignoring
-            // that for now.
-            0xa000000..=0xbffffff => get(&self.game_pak_rom, index - 0xa000000),
-            0xc000000..=0xdffffff => get(&self.game_pak_rom, index - 0xc000000),
-            // TODO: Cartridge SRAM
-            0xe000000..=0xe00ffff => T::zero(),
-            0x1000_0000..=0xffff_ffff => cpu.prefetched_instruction().as_(),
-            _ => T::zero(),
+            // The game pak is mirrored three times, each with its own wait states.
+            0x8000000..=0x9ffffff => self.read_rom(index - 0x8000000),
+            0xa000000..=0xbffffff => self.read_rom(index - 0xa000000),
+            0xd000000..=0xdffffff if self.is_eeprom_address(index) => {
+                get(&u32::from(self.backup.peek_eeprom()).to_le_bytes(), 0)
+            }
+            0xc000000..=0xdffffff => self.read_rom(index - 0xc000000),
+            // SRAM and Flash have an 8 bit bus, so wider reads see the same byte repeated.
+            0xe000000..=0xfffffff => T::from_le_bytes(&[self.backup.read_byte(index); N]),
+            _ => self.open_bus(index, cpu),
+        }
+    }
+
+    /// Reads past the end of the ROM see the low bits of the address left on the game pak bus,
+    /// as each halfword reads as its address divided by 2.
+    fn read_rom<T, const N: usize>(&self, offset: usize) -> T
+    where
+        T: FromBytes<Bytes = [u8; N]>,
+    {
+        if offset + N <= self.game_pak_rom.len() {
+            return get(&self.game_pak_rom, offset);
+        }
+
+        let mut bytes = [0; N];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let offset = offset + i;
+            *byte = match self.game_pak_rom.get(offset) {
+                Some(&byte) => byte,
+                None => ((offset / 2) as u16).to_le_bytes()[offset % 2],
+            };
+        }
+        T::from_le_bytes(&bytes)
+    }
+
+    /// Reads from unmapped memory see whatever the CPU last fetched.
+    fn open_bus<T, const N: usize>(&self, index: usize, cpu: &Cpu) -> T
+    where
+        T: FromBytes<Bytes = [u8; N]>,
+    {
+        get(&cpu.prefetched_instruction(self).to_le_bytes(), index & 3)
+    }
+
+    /// Reads from the CPU and DMA move the EEPROM on to its next bit, unlike peeks.
+    fn advance_eeprom(&self, index: u32) {
+        let index = index as usize;
+        if (0xd000000..=0xdffffff).contains(&index) && self.is_eeprom_address(index) {
+            self.backup.advance_eeprom();
         }
     }
 
     pub fn read(&self, index: u32, cpu: &Cpu) -> u32 {
-        let aligned_index = index & 0xfffffffc;
-        let value: u32 = self.read_internal(aligned_index, cpu);
-        value.rotate_right(8 * index.bits(0, 1))
+        self.add_access_cycles(index & 0xfffffffc, Width::Word);
+        let value = self.peek(index, cpu);
+        self.advance_eeprom(index);
+        value
     }
 
     pub fn read_half(&self, index: u32, cpu: &Cpu) -> u32 {
-        let aligned_index = index & 0xfffffffe;
-        let value: u16 = self.read_internal(aligned_index, cpu);
-        u32::from(value).rotate_right(8 * index.bit(0))
+        self.add_access_cycles(index & 0xfffffffe, Width::Half);
+        let value = self.peek_half(index, cpu);
+        self.advance_eeprom(index);
+        value
     }
 
     pub fn read_signed_half(&self, index: u32, cpu: &Cpu) -> u32 {
         let aligned_index = index & 0xfffffffe;
+        self.add_access_cycles(aligned_index, Width::Half);
         let value: u16 = self.read_internal(aligned_index, cpu);
+        self.advance_eeprom(index);
         let extended_value = i32::from(value as i16);
         extended_value.rotate_right(8 * index.bit(0)) as u32
     }
 
     pub fn read_byte(&self, index: u32, cpu: &Cpu) -> u8 {
-        self.read_internal(index, cpu)
+        self.add_access_cycles(index, Width::Byte);
+        let value = self.read_internal(index, cpu);
+        self.advance_eeprom(index);
+        value
+    }
+
+    /// Read a word without taking any cycles, for the debugger.
+    pub fn peek(&self, index: u32, cpu: &Cpu) -> u32 {
+        let aligned_index = index & 0xfffffffc;
+        let value: u32 = self.read_internal(aligned_index, cpu);
+        value.rotate_right(8 * index.bits(0, 1))
+    }
+
+    /// Read a halfword without taking any cycles, for the debugger and open bus.
+    pub fn peek_half(&self, index: u32, cpu: &Cpu) -> u32 {
+        let aligned_index = index & 0xfffffffe;
+        let value: u16 = self.read_internal(aligned_index, cpu);
+        u32::from(value).rotate_right(8 * index.bit(0))
     }
 
     fn write_internal<T, const N: usize>(&mut self, index: u32, value: T)
@@ -135,31 +403,127 @@ impl Bus {
             0x0000000..=0x1ffffff => {}
             0x2000000..=0x2ffffff => set(&mut self.ew_ram, index & 0x3ffff, value),
             0x3000000..=0x3ffffff => set(&mut self.iw_ram, index & 0x7fff, value),
-            0x4000000..=0x4ffffff => match index & 0x3ff {
-                0..=0x5f => self.ppu.write_lcd_io_regs(index & 0x40003ff, value),
-                0x60..=0x3fe => self.io_map.write(index & 0x40003ff, value),
-                0x3ff => todo!(),
-                _ => unreachable!(),
-            },
+            0x4000000..=0x400005f => self.ppu.write_lcd_io_regs(index, value),
+            0x4000060..=0x40003ff => self.io_map.write(index, value),
+            // Nothing is mapped past the IO registers, matching reads.
+            0x4000400..=0x4ffffff => {}
             0x5000000..=0x7ffffff => self.ppu.write_simple(index, value),
+            0xd000000..=0xdffffff if self.is_eeprom_address(index) => {
+                self.backup.write_eeprom(value.to_le_bytes()[0].into())
+            }
             // Cartridge ROM - read only?
             0x8000000..=0xdffffff => {}
-            // TODO: Cartridge SRAM
-            0xe000000..=0xe00ffff => {}
+            // Only the low byte of wider writes reaches SRAM and Flash.
+            0xe000000..=0xfffffff => self.backup.write_byte(index, value.to_le_bytes()[0]),
             0x1000_0000..=0xffff_ffff => {}
             _ => todo!("index {:#x} not implemented", index),
         }
     }
 
     pub fn write(&mut self, index: u32, value: u32) {
+        self.add_access_cycles(index & 0xfffffffc, Width::Word);
         self.write_internal(index & 0xfffffffc, value);
     }
 
     pub fn write_half(&mut self, index: u32, value: u16) {
+        self.add_access_cycles(index & 0xfffffffe, Width::Half);
         self.write_internal(index & 0xfffffffe, value);
     }
 
     pub fn write_byte(&mut self, index: u32, value: u8) {
+        self.add_access_cycles(index, Width::Byte);
         self.write_internal(index, value);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_past_end_of_rom_return_address_pattern() {
+        let mut bus = Bus::default();
+        let cpu = Cpu::default();
+        bus.load_rom(&[1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(bus.read(0x8000000, &cpu), 0x0403_0201);
+        assert_eq!(bus.read(0x8000004, &cpu), 0x0003_0605);
+        assert_eq!(bus.read(0x8000008, &cpu), 0x0005_0004);
+        assert_eq!(bus.read_half(0x9fffffe, &cpu), 0xffff);
+        assert_eq!(bus.read_byte(0x8000101, &cpu), 0x00);
+        assert_eq!(bus.read_byte(0x8000200, &cpu), 0x00);
+        assert_eq!(bus.read_byte(0x8000202, &cpu), 0x01);
+
+        // The wait state mirrors see the same ROM.
+        assert_eq!(bus.read(0xa000004, &cpu), 0x0003_0605);
+        assert_eq!(bus.read(0xc000008, &cpu), 0x0005_0004);
+    }
+
+    #[test]
+    fn writes_past_io_registers_are_ignored() {
+        let mut bus = Bus::default();
+        let cpu = Cpu::default();
+        bus.write_half(0x4001000, 0x0403);
+        bus.write_half(0x4001208, 0x0001);
+        bus.write(0x4000600, 0x3fff);
+
+        assert_eq!(bus.read_half(0x4000000, &cpu), 0);
+        assert_eq!(bus.read_half(0x4000208, &cpu), 0);
+        assert_eq!(bus.read_half(0x4000200, &cpu), 0);
+    }
+
+    #[test]
+    fn peeking_at_eeprom_leaves_read_in_progress() {
+        let mut bus = Bus::default();
+        let cpu = Cpu::default();
+        let mut rom = vec![0; 0x200];
+        rom[0x100..0x10b].copy_from_slice(b"EEPROM_V124");
+        bus.load_rom(&rom).unwrap();
+
+        // Read block 0, which is erased to all 1s, with a 14 bit address.
+        for bit in [1, 1].into_iter().chain([0; 15]) {
+            bus.write_half(0xd000000, bit);
+        }
+        for _ in 0..8 {
+            assert_eq!(bus.peek_half(0xd000000, &cpu), 0);
+        }
+        // The 4 junk bits are still to come.
+        for _ in 0..4 {
+            assert_eq!(bus.read_half(0xd000000, &cpu), 0);
+        }
+        assert_eq!(bus.peek_half(0xd000000, &cpu), 1);
+        assert_eq!(bus.read_half(0xd000000, &cpu), 1);
+    }
+
+    #[test]
+    fn prefetch_buffer_serves_rom_fetches() {
+        let mut bus = Bus::default();
+        let cpu = Cpu::default();
+        bus.load_rom(&[0; 0x100]).unwrap();
+        bus.write_half(0x4000204, 0x4000);
+        bus.take_access_cycles();
+
+        bus.fetch(0x8000000, &cpu);
+        assert_eq!(bus.take_access_cycles(), 8);
+        // Two halfwords are read while the CPU is busy with IWRAM.
+        bus.read(0x3000000, &cpu);
+        bus.idle(5);
+        bus.take_access_cycles();
+        bus.fetch(0x8000004, &cpu);
+        assert_eq!(bus.take_access_cycles(), 1);
+
+        // Data reads from the game pak empty the buffer.
+        bus.read(0x8000080, &cpu);
+        bus.idle(10);
+        bus.take_access_cycles();
+        bus.fetch(0x8000008, &cpu);
+        assert_eq!(bus.take_access_cycles(), 8);
+
+        // It's only used when enabled in WAITCNT.
+        bus.write_half(0x4000204, 0);
+        bus.idle(10);
+        bus.take_access_cycles();
+        bus.fetch(0x800000c, &cpu);
+        assert_eq!(bus.take_access_cycles(), 8);
+    }
+}
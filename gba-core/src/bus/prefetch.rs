@@ -0,0 +1,152 @@
+use super::waitcnt::{WaitControl, Width};
+
+/// The buffer holds up to 8 halfwords.
+const CAPACITY: u32 = 8;
+
+/// The game pak prefetch buffer, which reads opcodes ahead of the CPU while the game pak bus
+/// isn't being used for anything else.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Prefetch {
+    active: bool,
+    // The address of the next opcode the CPU should fetch.
+    head: u32,
+    // Halfwords which are ready in the buffer.
+    count: u32,
+    // Cycles spent so far reading the next halfword.
+    progress: u32,
+}
+
+impl Prefetch {
+    /// Start reading ahead from the given address.
+    pub fn restart(&mut self, address: u32) {
+        *self = Self {
+            active: true,
+            head: address,
+            count: 0,
+            progress: 0,
+        };
+    }
+
+    /// Stop reading ahead and empty the buffer, when the game pak bus is used for data.
+    pub fn stop(&mut self) {
+        self.active = false;
+    }
+
+    /// Read ahead for some cycles where the game pak bus would otherwise be idle.
+    pub fn idle(&mut self, mut cycles: u32, waitcnt: &WaitControl) {
+        if !self.active {
+            return;
+        }
+
+        while self.count < CAPACITY {
+            let address = self.head.wrapping_add(2 * self.count);
+            // WAITCNT can be lowered partway through a read, leaving it already finished.
+            let needed = waitcnt
+                .access_cycles(address, Width::Half, true)
+                .saturating_sub(self.progress);
+            if cycles < needed {
+                self.progress += cycles;
+                return;
+            }
+            cycles -= needed;
+            self.progress = 0;
+            self.count += 1;
+        }
+    }
+
+    /// Fetch an opcode through the buffer, returning the cycles taken, or `None` if the buffer
+    /// wasn't reading from that address. Opcodes which are already in the buffer only take one
+    /// cycle, otherwise the CPU waits for the halfword being read.
+    pub fn fetch(&mut self, address: u32, width: Width, waitcnt: &WaitControl) -> Option<u32> {
+        if !self.active || address != self.head {
+            return None;
+        }
+
+        let mut cycles = 0;
+        for _ in 0..width.bytes() / 2 {
+            if self.count > 0 {
+                self.count -= 1;
+            } else {
+                cycles += waitcnt
+                    .access_cycles(self.head, Width::Half, true)
+                    .saturating_sub(self.progress);
+                self.progress = 0;
+            }
+            self.head = self.head.wrapping_add(2);
+        }
+
+        if cycles == 0 {
+            // The game pak bus is free while the opcode comes out of the buffer.
+            self.idle(1, waitcnt);
+            cycles = 1;
+        }
+        Some(cycles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_while_idle() {
+        let waitcnt = WaitControl::default();
+        let mut prefetch = Prefetch::default();
+        prefetch.restart(0x8000002);
+
+        // Sequential halfwords take 3 cycles each by default.
+        prefetch.idle(7, &waitcnt);
+        assert_eq!(prefetch.fetch(0x8000002, Width::Half, &waitcnt), Some(1));
+        assert_eq!(prefetch.fetch(0x8000004, Width::Half, &waitcnt), Some(1));
+        // The buffer keeps reading while opcodes come out of it, but can't keep up.
+        assert_eq!(prefetch.fetch(0x8000006, Width::Half, &waitcnt), Some(1));
+        assert_eq!(prefetch.fetch(0x8000008, Width::Half, &waitcnt), Some(2));
+        assert_eq!(prefetch.fetch(0x8000100, Width::Half, &waitcnt), None);
+    }
+
+    #[test]
+    fn holds_eight_halfwords() {
+        let waitcnt = WaitControl::default();
+        let mut prefetch = Prefetch::default();
+        prefetch.restart(0x8000000);
+        prefetch.idle(1000, &waitcnt);
+
+        for address in (0x8000000..0x8000010).step_by(4) {
+            assert_eq!(prefetch.fetch(address, Width::Word, &waitcnt), Some(1));
+        }
+        // The idle cycles during those fetches only read one more halfword and part of another.
+        assert_eq!(prefetch.fetch(0x8000010, Width::Word, &waitcnt), Some(2));
+        assert_eq!(prefetch.fetch(0x8000014, Width::Word, &waitcnt), Some(6));
+    }
+
+    #[test]
+    fn stopped_by_data_access() {
+        let waitcnt = WaitControl::default();
+        let mut prefetch = Prefetch::default();
+        prefetch.restart(0x8000000);
+        prefetch.idle(100, &waitcnt);
+        prefetch.stop();
+        assert_eq!(prefetch.fetch(0x8000000, Width::Half, &waitcnt), None);
+    }
+
+    #[test]
+    fn lowering_waits_partway_through_a_read() {
+        let mut waitcnt = WaitControl::default();
+        let mut prefetch = Prefetch::default();
+        // WS2 sequential halfwords take 9 cycles by default.
+        prefetch.restart(0xc000000);
+        prefetch.idle(8, &waitcnt);
+        // Sequential WS2 accesses take 2 cycles.
+        waitcnt.write_byte(1, 0x04);
+        prefetch.idle(1, &waitcnt);
+        assert_eq!(prefetch.fetch(0xc000000, Width::Half, &waitcnt), Some(1));
+
+        // WS1 sequential halfwords take 5 cycles by default.
+        let mut waitcnt = WaitControl::default();
+        prefetch.restart(0xa000000);
+        prefetch.idle(4, &waitcnt);
+        waitcnt.write_byte(0, 0x80);
+        assert_eq!(prefetch.fetch(0xa000000, Width::Half, &waitcnt), Some(1));
+        assert_eq!(prefetch.fetch(0xa000002, Width::Half, &waitcnt), Some(1));
+    }
+}
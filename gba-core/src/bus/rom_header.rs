@@ -0,0 +1,151 @@
+#[cfg(feature = "debugger")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+const HEADER_SIZE: usize = 0xc0;
+
+/// The compressed Nintendo logo at 0x04, which the BIOS checks before booting a cartridge.
+const NINTENDO_LOGO: [u8; 156] = [
+    0x24, 0xff, 0xae, 0x51, 0x69, 0x9a, 0xa2, 0x21, 0x3d, 0x84, 0x82, 0x0a, 0x84, 0xe4, 0x09, 0xad,
+    0x11, 0x24, 0x8b, 0x98, 0xc0, 0x81, 0x7f, 0x21, 0xa3, 0x52, 0xbe, 0x19, 0x93, 0x09, 0xce, 0x20,
+    0x10, 0x46, 0x4a, 0x4a, 0xf8, 0x27, 0x31, 0xec, 0x58, 0xc7, 0xe8, 0x33, 0x82, 0xe3, 0xce, 0xbf,
+    0x85, 0xf4, 0xdf, 0x94, 0xce, 0x4b, 0x09, 0xc1, 0x94, 0x56, 0x8a, 0xc0, 0x13, 0x72, 0xa7, 0xfc,
+    0x9f, 0x84, 0x4d, 0x73, 0xa3, 0xca, 0x9a, 0x61, 0x58, 0x97, 0xa3, 0x27, 0xfc, 0x03, 0x98, 0x76,
+    0x23, 0x1d, 0xc7, 0x61, 0x03, 0x04, 0xae, 0x56, 0xbf, 0x38, 0x84, 0x00, 0x40, 0xa7, 0x0e, 0xfd,
+    0xff, 0x52, 0xfe, 0x03, 0x6f, 0x95, 0x30, 0xf1, 0x97, 0xfb, 0xc0, 0x85, 0x60, 0xd6, 0x80, 0x25,
+    0xa9, 0x63, 0xbe, 0x03, 0x01, 0x4e, 0x38, 0xe2, 0xf9, 0xa2, 0x34, 0xff, 0xbb, 0x3e, 0x03, 0x44,
+    0x78, 0x00, 0x90, 0xcb, 0x88, 0x11, 0x3a, 0x94, 0x65, 0xc0, 0x7c, 0x63, 0x87, 0xf0, 0x3c, 0xaf,
+    0xd6, 0x25, 0xe4, 0x8b, 0x38, 0x0a, 0xac, 0x72, 0x21, 0xd4, 0xf8, 0x07,
+];
+
+/// The cartridge header in the first 192 bytes of the ROM.
+#[cfg_attr(feature="debugger", wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomHeader {
+    title: String,
+    game_code: String,
+    maker_code: String,
+    unit_code: u8,
+    version: u8,
+    complement: u8,
+
+    logo_valid: bool,
+    expected_complement: u8,
+}
+
+impl RomHeader {
+    /// Decode the header, or return `None` if the ROM is too short to contain one.
+    pub fn parse(rom: &[u8]) -> Option<Self> {
+        let header = rom.get(..HEADER_SIZE)?;
+
+        Some(Self {
+            title: ascii(&header[0xa0..0xac]),
+            game_code: ascii(&header[0xac..0xb0]),
+            maker_code: ascii(&header[0xb0..0xb2]),
+            unit_code: header[0xb3],
+            version: header[0xbc],
+            complement: header[0xbd],
+
+            logo_valid: header[0x04..0xa0] == NINTENDO_LOGO,
+            expected_complement: complement(header),
+        })
+    }
+}
+
+#[cfg_attr(feature="debugger", wasm_bindgen)]
+impl RomHeader {
+    /// Up to 12 characters of uppercase ASCII.
+    #[cfg_attr(feature="debugger", wasm_bindgen(getter))]
+    pub fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    /// A 4 character code for the game and region, like "AXVE".
+    #[cfg_attr(feature="debugger", wasm_bindgen(getter))]
+    pub fn game_code(&self) -> String {
+        self.game_code.clone()
+    }
+
+    /// A 2 character code for the publisher, "01" for Nintendo.
+    #[cfg_attr(feature="debugger", wasm_bindgen(getter))]
+    pub fn maker_code(&self) -> String {
+        self.maker_code.clone()
+    }
+
+    /// 0 for the GBA.
+    #[cfg_attr(feature="debugger", wasm_bindgen(getter))]
+    pub fn unit_code(&self) -> u8 {
+        self.unit_code
+    }
+
+    #[cfg_attr(feature="debugger", wasm_bindgen(getter))]
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The header checksum stored at 0xBD.
+    #[cfg_attr(feature="debugger", wasm_bindgen(getter))]
+    pub fn complement(&self) -> u8 {
+        self.complement
+    }
+
+    /// Whether the stored complement checksum matches the header. The BIOS refuses to boot the
+    /// cartridge if it doesn't.
+    #[cfg_attr(feature="debugger", wasm_bindgen(getter))]
+    pub fn complement_valid(&self) -> bool {
+        self.complement == self.expected_complement
+    }
+
+    /// Whether the header contains the Nintendo logo, which the BIOS also checks.
+    #[cfg_attr(feature="debugger", wasm_bindgen(getter))]
+    pub fn logo_valid(&self) -> bool {
+        self.logo_valid
+    }
+}
+
+/// Decode a fixed length string field, which is padded with zeros.
+fn ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|&&byte| byte != 0)
+        .map(|&byte| match byte {
+            b' ' | 0x21..=0x7e => byte as char,
+            _ => '?',
+        })
+        .collect()
+}
+
+/// The checksum of 0xA0-0xBC which should be stored at 0xBD.
+fn complement(header: &[u8]) -> u8 {
+    header[0xa0..=0xbc]
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_sub(byte))
+        .wrapping_sub(0x19)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_test_rom_header() {
+        let header = RomHeader::parse(include_bytes!("../../tests/roms/panda.gba")).unwrap();
+        assert_eq!(header.title(), "PENE6942069");
+        assert_eq!(header.game_code(), "PENE");
+        assert_eq!(header.maker_code(), "PP");
+        assert_eq!(header.unit_code(), 0);
+        assert!(header.logo_valid());
+        assert!(header.complement_valid());
+    }
+
+    #[test]
+    fn detects_corrupt_header() {
+        let mut rom = include_bytes!("../../tests/roms/panda.gba").to_vec();
+        rom[0x10] ^= 1;
+        rom[0xa0] ^= 1;
+        let header = RomHeader::parse(&rom).unwrap();
+        assert!(!header.logo_valid());
+        assert!(!header.complement_valid());
+
+        assert_eq!(RomHeader::parse(&rom[..0xbf]), None);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::scheduler::{Event, Scheduler};
+use crate::utils::AddressableBits;
+
+use super::Interrupt;
+
+#[derive(Debug, Default)]
+struct Timer {
+    // Writes to TMxCNT_L set the reload value, reads return the current counter.
+    reload: [u8; 2],
+    cnt_h: [u8; 2],
+
+    // The counter at `start`. Timers driven by the prescaler work out the current value from the
+    // time instead of being incremented every cycle.
+    counter: u16,
+    start: u64,
+}
+
+impl Timer {
+    fn control(&self) -> u16 {
+        u16::from_le_bytes(self.cnt_h)
+    }
+
+    fn enabled(&self) -> bool {
+        self.control().bit(7) == 1
+    }
+
+    fn count_up(&self) -> bool {
+        self.control().bit(2) == 1
+    }
+
+    fn irq(&self) -> bool {
+        self.control().bit(6) == 1
+    }
+
+    fn prescaler(&self) -> u64 {
+        match self.control().bits(0, 1) {
+            0 => 1,
+            1 => 64,
+            2 => 256,
+            3 => 1024,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Timers {
+    timers: [Timer; 4],
+}
+
+impl Timers {
+    pub fn interrupt(timer: usize) -> Interrupt {
+        match timer {
+            0 => Interrupt::Timer0,
+            1 => Interrupt::Timer1,
+            2 => Interrupt::Timer2,
+            3 => Interrupt::Timer3,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Whether the timer is running from the prescaler. Count-up timers instead tick when the
+    /// previous timer overflows, which timer 0 can't do.
+    fn uses_prescaler(&self, timer: usize) -> bool {
+        let t = &self.timers[timer];
+        t.enabled() && (timer == 0 || !t.count_up())
+    }
+
+    fn counter(&self, timer: usize, now: u64) -> u16 {
+        let t = &self.timers[timer];
+        if !self.uses_prescaler(timer) {
+            return t.counter;
+        }
+
+        // The overflow event reloads the counter, so it never passes 0xffff here.
+        let increments = now.saturating_sub(t.start) / t.prescaler();
+        (u64::from(t.counter) + increments).min(0xffff) as u16
+    }
+
+    /// Schedule the timer's next overflow, or cancel it if the timer isn't using the prescaler.
+    fn schedule_overflow(&self, timer: usize, scheduler: &mut Scheduler) {
+        let event = Event::TimerOverflow(timer);
+        if self.uses_prescaler(timer) {
+            let t = &self.timers[timer];
+            let cycles = (0x10000 - u64::from(t.counter)) * t.prescaler();
+            scheduler.schedule_at(event, t.start + cycles);
+        } else {
+            scheduler.cancel(event);
+        }
+    }
+
+    /// Handle a timer overflowing at the given time, reloading it and incrementing any count-up
+    /// timers after it. Returns which timers overflowed with their IRQ enabled.
+    pub fn overflow(&mut self, timer: usize, time: u64, scheduler: &mut Scheduler) -> [bool; 4] {
+        let mut irqs = [false; 4];
+
+        let t = &mut self.timers[timer];
+        t.counter = u16::from_le_bytes(t.reload);
+        t.start = time;
+        irqs[timer] = t.irq();
+        self.schedule_overflow(timer, scheduler);
+
+        for (i, t) in self.timers.iter_mut().enumerate().skip(timer + 1) {
+            if !t.enabled() || !t.count_up() {
+                break;
+            }
+            if t.counter < 0xffff {
+                t.counter += 1;
+                break;
+            }
+            t.counter = u16::from_le_bytes(t.reload);
+            irqs[i] = t.irq();
+        }
+
+        irqs
+    }
+
+    pub fn read_byte(&self, index: usize, now: u64) -> u8 {
+        let offset = index - 0x4000100;
+        let timer = offset / 4;
+        match offset % 4 {
+            0..=1 => self.counter(timer, now).to_le_bytes()[offset % 4],
+            2..=3 => self.timers[timer].cnt_h[offset % 4 - 2],
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write_byte(&mut self, index: usize, value: u8, scheduler: &mut Scheduler) {
+        let offset = index - 0x4000100;
+        let timer = offset / 4;
+        match offset % 4 {
+            0..=1 => self.timers[timer].reload[offset % 4] = value,
+            2 => {
+                // Stop counting from the old settings.
+                let now = scheduler.now();
+                let counter = self.counter(timer, now);
+
+                let t = &mut self.timers[timer];
+                let was_enabled = t.enabled();
+                t.cnt_h[0] = value & 0xc7;
+                t.counter = counter;
+                t.start = now;
+                // The counter is reloaded when the timer is started.
+                if t.enabled() && !was_enabled {
+                    t.counter = u16::from_le_bytes(t.reload);
+                }
+                self.schedule_overflow(timer, scheduler);
+            }
+            // The upper byte of TMxCNT_H is unused.
+            3 => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Harness {
+        timers: Timers,
+        scheduler: Scheduler,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            Self {
+                timers: Timers::default(),
+                scheduler: Scheduler::default(),
+            }
+        }
+
+        fn write_half(&mut self, index: usize, value: u16) {
+            let [lo, hi] = value.to_le_bytes();
+            self.timers.write_byte(index, lo, &mut self.scheduler);
+            self.timers.write_byte(index + 1, hi, &mut self.scheduler);
+        }
+
+        fn read_half(&self, index: usize) -> u16 {
+            let now = self.scheduler.now();
+            u16::from_le_bytes([
+                self.timers.read_byte(index, now),
+                self.timers.read_byte(index + 1, now),
+            ])
+        }
+
+        /// Advance time, handling overflow events. Returns which timers raised an IRQ.
+        fn tick(&mut self, cycles: u64) -> [bool; 4] {
+            let mut irqs = [false; 4];
+            self.scheduler.advance(cycles);
+            while let Some((Event::TimerOverflow(timer), time)) = self.scheduler.pop_due() {
+                let overflowed = self.timers.overflow(timer, time, &mut self.scheduler);
+                for (irq, overflowed) in irqs.iter_mut().zip(overflowed) {
+                    *irq |= overflowed;
+                }
+            }
+            irqs
+        }
+    }
+
+    #[test]
+    fn prescaler_divides_cycles() {
+        let mut timers = Harness::new();
+        // 64 cycle prescaler.
+        timers.write_half(0x4000102, 0x81);
+
+        timers.tick(63);
+        assert_eq!(timers.read_half(0x4000100), 0);
+        timers.tick(1);
+        assert_eq!(timers.read_half(0x4000100), 1);
+        timers.tick(640);
+        assert_eq!(timers.read_half(0x4000100), 11);
+    }
+
+    #[test]
+    fn overflow_reloads_and_raises_irq() {
+        let mut timers = Harness::new();
+        timers.write_half(0x4000100, 0xfff0);
+        timers.write_half(0x4000102, 0xc0);
+        assert_eq!(timers.read_half(0x4000100), 0xfff0);
+
+        assert_eq!(timers.tick(15), [false; 4]);
+        assert_eq!(timers.tick(1), [true, false, false, false]);
+        assert_eq!(timers.read_half(0x4000100), 0xfff0);
+        // Several overflows can be handled at once.
+        timers.tick(40);
+        assert_eq!(timers.read_half(0x4000100), 0xfff8);
+    }
+
+    #[test]
+    fn count_up_timer_cascades() {
+        let mut timers = Harness::new();
+        timers.write_half(0x4000100, 0xff00);
+        timers.write_half(0x4000102, 0x80);
+        // Timer 1 counts up with IRQ enabled.
+        timers.write_half(0x4000104, 0xfffe);
+        timers.write_half(0x4000106, 0xc4);
+
+        assert_eq!(timers.tick(0x100), [false; 4]);
+        assert_eq!(timers.read_half(0x4000104), 0xffff);
+        assert_eq!(timers.tick(0x100), [false, true, false, false]);
+        assert_eq!(timers.read_half(0x4000104), 0xfffe);
+    }
+
+    #[test]
+    fn stopping_freezes_counter() {
+        let mut timers = Harness::new();
+        timers.write_half(0x4000102, 0x80);
+        timers.tick(100);
+        timers.write_half(0x4000102, 0);
+        timers.tick(100);
+        assert_eq!(timers.read_half(0x4000100), 100);
+        assert_eq!(timers.scheduler.next_event_time(), u64::MAX);
+    }
+}
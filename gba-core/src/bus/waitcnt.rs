@@ -0,0 +1,141 @@
+use crate::utils::AddressableBits;
+
+/// Wait states for the first access to a game pak region, selected by two bits of WAITCNT.
+const NON_SEQUENTIAL_WAITS: [u32; 4] = [4, 3, 2, 8];
+
+/// The size of an access, which decides how many times a narrow bus is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    Byte,
+    Half,
+    Word,
+}
+
+impl Width {
+    pub fn bytes(&self) -> u32 {
+        match *self {
+            Self::Byte => 1,
+            Self::Half => 2,
+            Self::Word => 4,
+        }
+    }
+}
+
+/// WAITCNT, which controls the wait states of SRAM and the three mirrors of the game pak ROM.
+#[derive(Debug, Default)]
+pub struct WaitControl {
+    waitcnt: u16,
+}
+
+impl WaitControl {
+    pub fn read_byte(&self, index: usize) -> u8 {
+        self.waitcnt.to_le_bytes()[index & 1]
+    }
+
+    pub fn write_byte(&mut self, index: usize, value: u8) {
+        let mut bytes = self.waitcnt.to_le_bytes();
+        bytes[index & 1] = value;
+        // Bit 15 is the read only game pak type flag, which is always 0 for GBA cartridges.
+        self.waitcnt = u16::from_le_bytes(bytes) & 0x5fff;
+    }
+
+    /// Whether the game pak prefetch buffer is enabled.
+    pub fn prefetch(&self) -> bool {
+        self.waitcnt.bit(14) == 1
+    }
+
+    /// Wait states for SRAM and Flash, which are never sequential.
+    fn sram_waits(&self) -> u32 {
+        NON_SEQUENTIAL_WAITS[self.waitcnt.bits(0, 1) as usize]
+    }
+
+    /// Wait states for a halfword access to game pak ROM wait state region 0, 1 or 2.
+    fn rom_waits(&self, region: usize, sequential: bool) -> u32 {
+        let (first_bit, sequential_bit, slow_sequential) = match region {
+            0 => (2, 4, 2),
+            1 => (5, 7, 4),
+            2 => (8, 10, 8),
+            _ => unreachable!(),
+        };
+
+        if !sequential {
+            NON_SEQUENTIAL_WAITS[self.waitcnt.bits(first_bit, first_bit + 1) as usize]
+        } else if self.waitcnt.bit(sequential_bit) == 1 {
+            1
+        } else {
+            slow_sequential
+        }
+    }
+
+    /// The number of cycles an access takes, including wait states.
+    pub fn access_cycles(&self, address: u32, width: Width, sequential: bool) -> u32 {
+        match address >> 24 {
+            // EWRAM has a 16 bit bus with 2 wait states.
+            0x02 => match width {
+                Width::Word => 6,
+                _ => 3,
+            },
+            // Palette RAM and VRAM have 16 bit buses.
+            0x05 | 0x06 => match width {
+                Width::Word => 2,
+                _ => 1,
+            },
+            0x08..=0x0d => {
+                let region = ((address >> 25) - 4) as usize;
+                // The game pak bus is 16 bits wide, so words are read as two halfwords where the
+                // second is always sequential.
+                let first = 1 + self.rom_waits(region, sequential);
+                match width {
+                    Width::Word => first + 1 + self.rom_waits(region, true),
+                    _ => first,
+                }
+            }
+            // SRAM has an 8 bit bus, but only one byte is ever transferred.
+            0x0e | 0x0f => 1 + self.sram_waits(),
+            // BIOS, IWRAM, IO and OAM are all 32 bits wide without wait states.
+            _ => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_rom_timing() {
+        let waitcnt = WaitControl::default();
+        assert_eq!(waitcnt.access_cycles(0x8000000, Width::Half, false), 5);
+        assert_eq!(waitcnt.access_cycles(0x8000002, Width::Half, true), 3);
+        assert_eq!(waitcnt.access_cycles(0x8000000, Width::Word, false), 8);
+        assert_eq!(waitcnt.access_cycles(0x8000004, Width::Word, true), 6);
+        assert_eq!(waitcnt.access_cycles(0xa000000, Width::Half, true), 5);
+        assert_eq!(waitcnt.access_cycles(0xc000000, Width::Half, true), 9);
+        assert_eq!(waitcnt.access_cycles(0xe000000, Width::Word, false), 5);
+    }
+
+    #[test]
+    fn configured_rom_timing() {
+        let mut waitcnt = WaitControl::default();
+        // The setting most games use: 3,1 for WS0 and 8 cycles for SRAM.
+        waitcnt.write_byte(0x4000204, 0x17);
+        waitcnt.write_byte(0x4000205, 0xc0);
+        assert_eq!(waitcnt.read_byte(0x4000205), 0x40);
+        assert!(waitcnt.prefetch());
+
+        assert_eq!(waitcnt.access_cycles(0x8000000, Width::Half, false), 4);
+        assert_eq!(waitcnt.access_cycles(0x8000000, Width::Word, false), 6);
+        assert_eq!(waitcnt.access_cycles(0x9000004, Width::Word, true), 4);
+        assert_eq!(waitcnt.access_cycles(0xe000000, Width::Byte, false), 9);
+    }
+
+    #[test]
+    fn internal_bus_widths() {
+        let waitcnt = WaitControl::default();
+        assert_eq!(waitcnt.access_cycles(0x2000000, Width::Half, false), 3);
+        assert_eq!(waitcnt.access_cycles(0x2000000, Width::Word, true), 6);
+        assert_eq!(waitcnt.access_cycles(0x6000000, Width::Word, false), 2);
+        assert_eq!(waitcnt.access_cycles(0x7000000, Width::Word, false), 1);
+        assert_eq!(waitcnt.access_cycles(0x3000000, Width::Word, false), 1);
+    }
+}
@@ -0,0 +1,677 @@
+//! High-level emulation of BIOS software interrupts.
+//!
+//! When enabled, supported SWIs are executed natively instead of jumping to the BIOS. Anything
+//! not listed here still goes through the BIOS image, as do IRQs, which always enter through the
+//! BIOS vector at 0x18.
+
+use crate::bus::{Bus, LATCH_AFTER_SWI};
+use crate::utils::AddressableBits;
+
+use super::{Cpu, Mode, State};
+
+/// The interrupt flags the BIOS IRQ handler sets for IntrWait, mirrored at 0x3fffff8.
+const BIOS_IF: u32 = 0x3007ff8;
+
+/// The BIOS sine table, with 256 steps per turn in 1.14 fixed point. Values are rounded towards
+/// zero.
+const SINE_TABLE: [i16; 256] = [
+    0, 402, 803, 1205, 1605, 2005, 2404, 2801,
+    3196, 3589, 3980, 4369, 4756, 5139, 5519, 5896,
+    6269, 6639, 7005, 7366, 7723, 8075, 8423, 8765,
+    9102, 9434, 9759, 10079, 10393, 10701, 11002, 11297,
+    11585, 11866, 12139, 12406, 12665, 12916, 13159, 13395,
+    13622, 13842, 14053, 14255, 14449, 14634, 14810, 14978,
+    15136, 15286, 15426, 15557, 15678, 15790, 15892, 15985,
+    16069, 16142, 16206, 16260, 16305, 16339, 16364, 16379,
+    16384, 16379, 16364, 16339, 16305, 16260, 16206, 16142,
+    16069, 15985, 15892, 15790, 15678, 15557, 15426, 15286,
+    15136, 14978, 14810, 14634, 14449, 14255, 14053, 13842,
+    13622, 13395, 13159, 12916, 12665, 12406, 12139, 11866,
+    11585, 11297, 11002, 10701, 10393, 10079, 9759, 9434,
+    9102, 8765, 8423, 8075, 7723, 7366, 7005, 6639,
+    6269, 5896, 5519, 5139, 4756, 4369, 3980, 3589,
+    3196, 2801, 2404, 2005, 1605, 1205, 803, 402,
+    0, -402, -803, -1205, -1605, -2005, -2404, -2801,
+    -3196, -3589, -3980, -4369, -4756, -5139, -5519, -5896,
+    -6269, -6639, -7005, -7366, -7723, -8075, -8423, -8765,
+    -9102, -9434, -9759, -10079, -10393, -10701, -11002, -11297,
+    -11585, -11866, -12139, -12406, -12665, -12916, -13159, -13395,
+    -13622, -13842, -14053, -14255, -14449, -14634, -14810, -14978,
+    -15136, -15286, -15426, -15557, -15678, -15790, -15892, -15985,
+    -16069, -16142, -16206, -16260, -16305, -16339, -16364, -16379,
+    -16384, -16379, -16364, -16339, -16305, -16260, -16206, -16142,
+    -16069, -15985, -15892, -15790, -15678, -15557, -15426, -15286,
+    -15136, -14978, -14810, -14634, -14449, -14255, -14053, -13842,
+    -13622, -13395, -13159, -12916, -12665, -12406, -12139, -11866,
+    -11585, -11297, -11002, -10701, -10393, -10079, -9759, -9434,
+    -9102, -8765, -8423, -8075, -7723, -7366, -7005, -6639,
+    -6269, -5896, -5519, -5139, -4756, -4369, -3980, -3589,
+    -3196, -2801, -2404, -2005, -1605, -1205, -803, -402,
+];
+
+impl Cpu {
+    /// Run the given BIOS function natively. Returns false if the function isn't supported, in
+    /// which case the SWI should be handled by the BIOS.
+    pub(crate) fn hle_swi(&mut self, bus: &mut Bus, function: u32) -> bool {
+        match function {
+            0x00 => self.hle_soft_reset(bus),
+            0x01 => self.hle_register_ram_reset(bus),
+            // HALTCNT
+            0x02 => bus.write_byte(0x4000301, 0),
+            0x03 => bus.write_byte(0x4000301, 0x80),
+            0x04 => {
+                let discard_old = self.get_reg(0) != 0;
+                self.hle_intr_wait(bus, discard_old, self.get_reg(1) as u16);
+            }
+            0x05 => self.hle_intr_wait(bus, true, 1),
+            0x06 => self.hle_div(self.get_reg(0), self.get_reg(1)),
+            0x07 => self.hle_div(self.get_reg(1), self.get_reg(0)),
+            0x08 => self.set_reg(0, sqrt(self.get_reg(0))),
+            0x09 => {
+                let tan = self.get_reg(0) as i32;
+                self.set_reg(0, arc_tan(tan) as u32);
+            }
+            0x0a => {
+                let x = self.get_reg(0) as i32;
+                let y = self.get_reg(1) as i32;
+                self.set_reg(0, arc_tan2(x, y));
+            }
+            0x0b => self.hle_cpu_set(bus),
+            0x0c => self.hle_cpu_fast_set(bus),
+            0x0e => self.hle_bg_affine_set(bus),
+            0x0f => self.hle_obj_affine_set(bus),
+            0x11 => self.hle_lz77(bus, false),
+            0x12 => self.hle_lz77(bus, true),
+            0x13 => self.hle_huffman(bus),
+            0x14 => self.hle_run_length(bus, false),
+            0x15 => self.hle_run_length(bus, true),
+            _ => return false,
+        }
+        // Leave the latch as if the BIOS had returned from the call.
+        bus.bios_latch = LATCH_AFTER_SWI;
+        true
+    }
+
+    fn hle_soft_reset(&mut self, bus: &mut Bus) {
+        // The return address flag is read before the top of IWRAM is cleared.
+        let to_ewram = bus.read_byte(0x3007ffa, self) != 0;
+        for address in (0x3007e00..0x3008000).step_by(4) {
+            bus.write(address, 0);
+        }
+
+        for reg in 0..13 {
+            self.set_reg_with_mode(reg, Mode::System, 0);
+        }
+        for mode in [Mode::Supervisor, Mode::IRQ] {
+            self.set_reg_with_mode(14, mode, 0);
+        }
+        self.regs.spsr_svc = 0;
+        self.regs.spsr_irq = 0;
+
+        self.skip_bios();
+        if to_ewram {
+            self.set_reg_with_mode(15, Mode::System, 0x2000000);
+        }
+        self.flush_pipeline();
+    }
+
+    fn hle_register_ram_reset(&mut self, bus: &mut Bus) {
+        let flags = self.get_reg(0);
+
+        let clear = |bus: &mut Bus, start: u32, end: u32| {
+            for address in (start..end).step_by(4) {
+                bus.write(address, 0);
+            }
+        };
+
+        // Forced blank is always set.
+        bus.write_half(0x4000000, 0x80);
+
+        if flags.bit(0) == 1 {
+            clear(bus, 0x2000000, 0x2040000);
+        }
+        if flags.bit(1) == 1 {
+            // The stacks and BIOS variables at the top of IWRAM are kept.
+            clear(bus, 0x3000000, 0x3007e00);
+        }
+        if flags.bit(2) == 1 {
+            clear(bus, 0x5000000, 0x5000400);
+        }
+        if flags.bit(3) == 1 {
+            clear(bus, 0x6000000, 0x6018000);
+        }
+        if flags.bit(4) == 1 {
+            clear(bus, 0x7000000, 0x7000400);
+        }
+        if flags.bit(5) == 1 {
+            clear(bus, 0x4000120, 0x4000130);
+            // RCNT resets to general purpose mode.
+            bus.write_half(0x4000134, 0x8000);
+            clear(bus, 0x4000140, 0x4000160);
+        }
+        if flags.bit(6) == 1 {
+            clear(bus, 0x4000060, 0x40000b0);
+        }
+        if flags.bit(7) == 1 {
+            clear(bus, 0x4000004, 0x4000060);
+            clear(bus, 0x40000b0, 0x4000120);
+            bus.write_half(0x4000132, 0);
+            clear(bus, 0x4000200, 0x400020c);
+        }
+    }
+
+    /// Wait until one of the given interrupts is flagged in BIOS_IF. Rather than looping inside
+    /// the BIOS, the CPU halts and the SWI is executed again once an interrupt has been handled.
+    fn hle_intr_wait(&mut self, bus: &mut Bus, discard_old: bool, flags: u16) {
+        bus.write_byte(0x4000208, 1);
+
+        let bios_if = bus.read_half(BIOS_IF, self) as u16;
+        // Old flags are only discarded the first time through.
+        if discard_old && !self.hle_intr_waiting {
+            bus.write_half(BIOS_IF, bios_if & !flags);
+        } else if bios_if & flags != 0 {
+            bus.write_half(BIOS_IF, bios_if & !flags);
+            self.hle_intr_waiting = false;
+            return;
+        }
+
+        self.hle_intr_waiting = true;
+        bus.write_byte(0x4000301, 0);
+
+        let swi_address = match self.get_state() {
+            State::ARM => self.get_reg(15) - 8,
+            State::Thumb => self.get_reg(15) - 4,
+        };
+        self.set_reg(15, swi_address);
+        self.flush_pipeline();
+    }
+
+    fn hle_div(&mut self, numerator: u32, denominator: u32) {
+        let numerator = numerator as i32;
+        let denominator = denominator as i32;
+        // The real BIOS never returns when dividing by zero.
+        if denominator == 0 {
+            return;
+        }
+
+        let quotient = numerator.wrapping_div(denominator);
+        self.set_reg(0, quotient as u32);
+        self.set_reg(1, numerator.wrapping_rem(denominator) as u32);
+        self.set_reg(3, quotient.unsigned_abs());
+    }
+
+    fn hle_cpu_set(&mut self, bus: &mut Bus) {
+        let mut source = self.get_reg(0);
+        let mut destination = self.get_reg(1);
+        let control = self.get_reg(2);
+
+        let count = control.bits(0, 20);
+        let fill = control.bit(24) == 1;
+        let word = control.bit(26) == 1;
+
+        if word {
+            source &= !3;
+            destination &= !3;
+            for _ in 0..count {
+                let value = bus.read(source, self);
+                bus.write(destination, value);
+                if !fill {
+                    source = source.wrapping_add(4);
+                }
+                destination = destination.wrapping_add(4);
+            }
+        } else {
+            source &= !1;
+            destination &= !1;
+            for _ in 0..count {
+                let value = bus.read_half(source, self) as u16;
+                bus.write_half(destination, value);
+                if !fill {
+                    source = source.wrapping_add(2);
+                }
+                destination = destination.wrapping_add(2);
+            }
+        }
+    }
+
+    fn hle_cpu_fast_set(&mut self, bus: &mut Bus) {
+        let mut source = self.get_reg(0) & !3;
+        let mut destination = self.get_reg(1) & !3;
+        let control = self.get_reg(2);
+
+        // Words are always copied in blocks of 8.
+        let count = (control.bits(0, 20) + 7) & !7;
+        let fill = control.bit(24) == 1;
+
+        let fill_value = bus.read(source, self);
+        for _ in 0..count {
+            let value = if fill {
+                fill_value
+            } else {
+                bus.read(source, self)
+            };
+            bus.write(destination, value);
+            source = source.wrapping_add(4);
+            destination = destination.wrapping_add(4);
+        }
+    }
+
+    fn hle_bg_affine_set(&mut self, bus: &mut Bus) {
+        let mut source = self.get_reg(0);
+        let mut destination = self.get_reg(1);
+        let count = self.get_reg(2);
+
+        for _ in 0..count {
+            // Texture coordinates of the rotation center, in 19.8 fixed point.
+            let origin_x = bus.read(source, self) as i32;
+            let origin_y = bus.read(source.wrapping_add(4), self) as i32;
+            // Screen coordinates of the rotation center.
+            let center_x = i32::from(bus.read_half(source.wrapping_add(8), self) as i16);
+            let center_y = i32::from(bus.read_half(source.wrapping_add(10), self) as i16);
+            let scale_x = i32::from(bus.read_half(source.wrapping_add(12), self) as i16);
+            let scale_y = i32::from(bus.read_half(source.wrapping_add(14), self) as i16);
+            let angle = bus.read_half(source.wrapping_add(16), self);
+
+            let [pa, pb, pc, pd] = affine_matrix(scale_x, scale_y, angle);
+            let x = origin_x
+                .wrapping_sub(pa.wrapping_mul(center_x))
+                .wrapping_sub(pb.wrapping_mul(center_y));
+            let y = origin_y
+                .wrapping_sub(pc.wrapping_mul(center_x))
+                .wrapping_sub(pd.wrapping_mul(center_y));
+
+            for (i, parameter) in [pa, pb, pc, pd].into_iter().enumerate() {
+                bus.write_half(destination.wrapping_add(2 * i as u32), parameter as u16);
+            }
+            bus.write(destination.wrapping_add(8), x as u32);
+            bus.write(destination.wrapping_add(12), y as u32);
+
+            source = source.wrapping_add(20);
+            destination = destination.wrapping_add(16);
+        }
+    }
+
+    fn hle_obj_affine_set(&mut self, bus: &mut Bus) {
+        let mut source = self.get_reg(0);
+        let mut destination = self.get_reg(1);
+        let count = self.get_reg(2);
+        // Distance between each parameter, 2 for a packed matrix or 8 to write straight to OAM.
+        let stride = self.get_reg(3);
+
+        for _ in 0..count {
+            let scale_x = i32::from(bus.read_half(source, self) as i16);
+            let scale_y = i32::from(bus.read_half(source.wrapping_add(2), self) as i16);
+            let angle = bus.read_half(source.wrapping_add(4), self);
+
+            for parameter in affine_matrix(scale_x, scale_y, angle) {
+                bus.write_half(destination, parameter as u16);
+                destination = destination.wrapping_add(stride);
+            }
+
+            source = source.wrapping_add(8);
+        }
+    }
+
+    /// Read the 32-bit header of compressed data, returning the decompressed size.
+    fn decompressed_size(&self, bus: &Bus, source: u32) -> usize {
+        bus.read(source & !3, self).bits(8, 31) as usize
+    }
+
+    /// Write decompressed data. VRAM can't be written a byte at a time, so the VRAM variants
+    /// write halfwords.
+    fn write_decompressed(&self, bus: &mut Bus, destination: u32, data: &[u8], vram: bool) {
+        if vram {
+            for (i, pair) in data.chunks(2).enumerate() {
+                let value = u16::from(pair[0]) | (u16::from(*pair.get(1).unwrap_or(&0)) << 8);
+                bus.write_half(destination.wrapping_add(2 * i as u32), value);
+            }
+        } else {
+            for (i, &byte) in data.iter().enumerate() {
+                bus.write_byte(destination.wrapping_add(i as u32), byte);
+            }
+        }
+    }
+
+    fn hle_lz77(&mut self, bus: &mut Bus, vram: bool) {
+        let mut source = self.get_reg(0);
+        let destination = self.get_reg(1);
+
+        let size = self.decompressed_size(bus, source);
+        source = (source & !3).wrapping_add(4);
+
+        let mut data: Vec<u8> = Vec::with_capacity(size);
+        while data.len() < size {
+            let flags = bus.read_byte(source, self);
+            source = source.wrapping_add(1);
+
+            for block in (0..8).rev() {
+                if data.len() >= size {
+                    break;
+                }
+
+                if flags.bit(block) == 0 {
+                    data.push(bus.read_byte(source, self));
+                    source = source.wrapping_add(1);
+                } else {
+                    let high = bus.read_byte(source, self);
+                    let low = bus.read_byte(source.wrapping_add(1), self);
+                    source = source.wrapping_add(2);
+
+                    let length = usize::from(high.bits(4, 7)) + 3;
+                    let displacement = (usize::from(high.bits(0, 3)) << 8 | usize::from(low)) + 1;
+                    for _ in 0..length {
+                        let byte = data.len().checked_sub(displacement).map_or(0, |i| data[i]);
+                        data.push(byte);
+                    }
+                }
+            }
+        }
+        data.truncate(size);
+
+        self.write_decompressed(bus, destination, &data, vram);
+    }
+
+    fn hle_run_length(&mut self, bus: &mut Bus, vram: bool) {
+        let mut source = self.get_reg(0);
+        let destination = self.get_reg(1);
+
+        let size = self.decompressed_size(bus, source);
+        source = (source & !3).wrapping_add(4);
+
+        let mut data: Vec<u8> = Vec::with_capacity(size);
+        while data.len() < size {
+            let flag = bus.read_byte(source, self);
+            source = source.wrapping_add(1);
+
+            if flag.bit(7) == 1 {
+                let length = usize::from(flag.bits(0, 6)) + 3;
+                let byte = bus.read_byte(source, self);
+                source = source.wrapping_add(1);
+                data.resize(data.len() + length, byte);
+            } else {
+                let length = u32::from(flag.bits(0, 6)) + 1;
+                for i in 0..length {
+                    data.push(bus.read_byte(source.wrapping_add(i), self));
+                }
+                source = source.wrapping_add(length);
+            }
+        }
+        data.truncate(size);
+
+        self.write_decompressed(bus, destination, &data, vram);
+    }
+
+    fn hle_huffman(&mut self, bus: &mut Bus) {
+        let source = self.get_reg(0) & !3;
+        let mut destination = self.get_reg(1) & !3;
+
+        let header = bus.read(source, self);
+        let data_size = header.bits(0, 3);
+        let size = header.bits(8, 31);
+        // Symbols can only be 1, 2, 4 or 8 bits. Anything else is left undecompressed.
+        if !matches!(data_size, 1 | 2 | 4 | 8) {
+            return;
+        }
+
+        let tree_size = u32::from(bus.read_byte(source.wrapping_add(4), self));
+        let root = source.wrapping_add(5);
+        let mut bitstream = source.wrapping_add(4 + 2 * (tree_size + 1));
+
+        let mut node = root;
+        // Wide enough to hold a symbol past the end of a full word.
+        let mut output: u64 = 0;
+        let mut output_bits = 0;
+        let mut written = 0;
+        // A tree without reachable data nodes would never finish, so give up after 64 bits of
+        // bitstream for every output byte, which is more than any real tree needs.
+        let mut words_left = 2 * size;
+
+        while written < size && words_left > 0 {
+            words_left -= 1;
+            let bits = bus.read(bitstream, self);
+            bitstream = bitstream.wrapping_add(4);
+
+            for bit in (0..32).rev() {
+                let value = bus.read_byte(node, self);
+                let offset = u32::from(value.bits(0, 5));
+                let child = (node & !1).wrapping_add(2 * offset + 2 + bits.bit(bit));
+                // Bit 7 marks the left child as data, bit 6 the right child.
+                let is_data = value.bit(7 - bits.bit(bit) as usize) == 1;
+
+                if !is_data {
+                    node = child;
+                    continue;
+                }
+
+                let symbol = u64::from(bus.read_byte(child, self)) & ((1 << data_size) - 1);
+                output |= symbol << output_bits;
+                output_bits += data_size;
+                node = root;
+
+                if output_bits >= 32 {
+                    bus.write(destination, output as u32);
+                    destination = destination.wrapping_add(4);
+                    written += 4;
+                    output >>= 32;
+                    output_bits -= 32;
+                    if written >= size {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn sqrt(value: u32) -> u32 {
+    // Integer square root, rounded down.
+    let value = u64::from(value);
+    let mut root = (value as f64).sqrt() as u64;
+    while root * root > value {
+        root -= 1;
+    }
+    while (root + 1) * (root + 1) <= value {
+        root += 1;
+    }
+    root as u32
+}
+
+/// The BIOS polynomial approximation of arctan, taking and returning 1.14 fixed point values.
+fn arc_tan(tan: i32) -> i32 {
+    let a = -(tan.wrapping_mul(tan) >> 14);
+    let mut b = ((0xa9 * a) >> 14) + 0x390;
+    for coefficient in [0x91c, 0xfb6, 0x16aa, 0x2081, 0x3651, 0xa2f9] {
+        b = (b.wrapping_mul(a) >> 14) + coefficient;
+    }
+    tan.wrapping_mul(b) >> 16
+}
+
+/// Angle of the point (x, y), where 0x10000 is a full turn, using the same octant reduction as
+/// the BIOS. Like the BIOS, angles just below a full turn can come out as 0x10000.
+fn arc_tan2(x: i32, y: i32) -> u32 {
+    let angle = if y == 0 {
+        if x >= 0 {
+            0
+        } else {
+            0x8000
+        }
+    } else if x == 0 {
+        if y >= 0 {
+            0x4000
+        } else {
+            0xc000
+        }
+    } else if y >= 0 {
+        if x >= 0 && x >= y {
+            arc_tan((y << 14).wrapping_div(x))
+        } else if x < 0 && x.unsigned_abs() >= y as u32 {
+            arc_tan((y << 14).wrapping_div(x)) + 0x8000
+        } else {
+            0x4000 - arc_tan((x << 14).wrapping_div(y))
+        }
+    } else if x <= 0 && x.unsigned_abs() >= y.unsigned_abs() {
+        arc_tan((y << 14).wrapping_div(x)) + 0x8000
+    } else if x > 0 && x as u32 >= y.unsigned_abs() {
+        arc_tan((y << 14).wrapping_div(x)) + 0x10000
+    } else {
+        0xc000 - arc_tan((x << 14).wrapping_div(y))
+    };
+    angle as u32
+}
+
+/// PA, PB, PC and PD for 8.8 fixed point scales and an angle where 0x10000 is a full turn, as
+/// 8.8 fixed point values. Only the top 8 bits of the angle are used.
+fn affine_matrix(scale_x: i32, scale_y: i32, angle: u32) -> [i32; 4] {
+    let index = angle.bits(8, 15) as usize;
+    let sin = i32::from(SINE_TABLE[index]);
+    let cos = i32::from(SINE_TABLE[(index + 0x40) & 0xff]);
+    [
+        (scale_x * cos) >> 14,
+        -((scale_x * sin) >> 14),
+        (scale_y * sin) >> 14,
+        (scale_y * cos) >> 14,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(sqrt(0), 0);
+        assert_eq!(sqrt(15), 3);
+        assert_eq!(sqrt(16), 4);
+        assert_eq!(sqrt(u32::MAX), 0xffff);
+    }
+
+    #[test]
+    fn test_arc_tan2_axes() {
+        assert_eq!(arc_tan2(1, 0), 0);
+        assert_eq!(arc_tan2(0, 1), 0x4000);
+        assert_eq!(arc_tan2(-1, 0), 0x8000);
+        assert_eq!(arc_tan2(0, -1), 0xc000);
+        // 45 degrees, with some error from the approximation.
+        assert!(arc_tan2(0x100, 0x100).abs_diff(0x2000) < 8);
+    }
+
+    #[test]
+    fn test_lz77() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+
+        // "abcabcabc": 3 literals then a back reference of length 6, displacement 3.
+        let compressed = [0x10, 9, 0, 0, 0b0001_0000, b'a', b'b', b'c', 0x30, 0x02];
+        for (i, &byte) in compressed.iter().enumerate() {
+            bus.write_byte(0x2000000 + i as u32, byte);
+        }
+        cpu.set_reg_with_mode(0, Mode::User, 0x2000000);
+        cpu.set_reg_with_mode(1, Mode::User, 0x3000000);
+        assert!(cpu.hle_swi(&mut bus, 0x11));
+
+        let result: Vec<u8> = (0..9)
+            .map(|i| bus.read_byte(0x3000000 + i, &cpu))
+            .collect();
+        assert_eq!(result, b"abcabcabc");
+    }
+
+    #[test]
+    fn test_run_length() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+
+        // 2 raw bytes, then 'z' repeated 4 times.
+        let compressed = [0x30, 6, 0, 0, 0x01, b'x', b'y', 0x81, b'z'];
+        for (i, &byte) in compressed.iter().enumerate() {
+            bus.write_byte(0x2000000 + i as u32, byte);
+        }
+        cpu.set_reg_with_mode(0, Mode::User, 0x2000000);
+        cpu.set_reg_with_mode(1, Mode::User, 0x3000000);
+        assert!(cpu.hle_swi(&mut bus, 0x14));
+
+        let result: Vec<u8> = (0..6)
+            .map(|i| bus.read_byte(0x3000000 + i, &cpu))
+            .collect();
+        assert_eq!(result, b"xyzzzz");
+    }
+
+    #[test]
+    fn test_huffman_rejects_bad_data_size() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+
+        // 3 bit symbols, with a tree whose root has two data children.
+        let compressed = [0x23, 4, 0, 0, 1, 0xc0, b'a', b'b', 0, 0, 0, 0];
+        for (i, &byte) in compressed.iter().enumerate() {
+            bus.write_byte(0x2000000 + i as u32, byte);
+        }
+        bus.write(0x3000000, 0x12345678);
+        cpu.set_reg_with_mode(0, Mode::User, 0x2000000);
+        cpu.set_reg_with_mode(1, Mode::User, 0x3000000);
+        assert!(cpu.hle_swi(&mut bus, 0x13));
+        assert_eq!(bus.read(0x3000000, &cpu), 0x12345678);
+
+        // A size of 0 would never fill a word.
+        bus.write_byte(0x2000000, 0x20);
+        assert!(cpu.hle_swi(&mut bus, 0x13));
+        assert_eq!(bus.read(0x3000000, &cpu), 0x12345678);
+    }
+
+    #[test]
+    fn test_huffman_gives_up_without_data_nodes() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+
+        // 8 bit symbols, with an all zero tree where every node leads to another node.
+        let compressed = [0x28, 4, 0, 0, 1, 0, 0, 0];
+        for (i, &byte) in compressed.iter().enumerate() {
+            bus.write_byte(0x2000000 + i as u32, byte);
+        }
+        bus.write(0x3000000, 0x12345678);
+        cpu.set_reg_with_mode(0, Mode::User, 0x2000000);
+        cpu.set_reg_with_mode(1, Mode::User, 0x3000000);
+        assert!(cpu.hle_swi(&mut bus, 0x13));
+        assert_eq!(bus.read(0x3000000, &cpu), 0x12345678);
+    }
+
+    #[test]
+    fn test_huffman() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+
+        // 8 bit symbols, with a tree whose root has data children 'a' (0) and 'b' (1).
+        let compressed = [0x28, 4, 0, 0, 1, 0xc0, b'a', b'b', 0, 0, 0, 0b1001_0000];
+        for (i, &byte) in compressed.iter().enumerate() {
+            bus.write_byte(0x2000000 + i as u32, byte);
+        }
+        cpu.set_reg_with_mode(0, Mode::User, 0x2000000);
+        cpu.set_reg_with_mode(1, Mode::User, 0x3000000);
+        assert!(cpu.hle_swi(&mut bus, 0x13));
+
+        let result: Vec<u8> = (0..4)
+            .map(|i| bus.read_byte(0x3000000 + i, &cpu))
+            .collect();
+        assert_eq!(result, b"baab");
+    }
+
+    #[test]
+    fn test_cpu_set_wraps_around_address_space() {
+        let mut cpu = Cpu::default();
+        let mut bus = Bus::default();
+
+        // Copy 3 words from the end of the address space, which wraps around to the start of the
+        // BIOS. The CPU is still in the BIOS, so it can be read.
+        cpu.set_reg_with_mode(0, Mode::User, 0xfffffffc);
+        cpu.set_reg_with_mode(1, Mode::User, 0x2000000);
+        cpu.set_reg_with_mode(2, Mode::User, 1 << 26 | 3);
+        assert!(cpu.hle_swi(&mut bus, 0x0b));
+
+        let bios = bus.bios();
+        let bios_word = |i: usize| u32::from_le_bytes(bios[i..i + 4].try_into().unwrap());
+        let (first, second) = (bios_word(0), bios_word(4));
+        assert_eq!(bus.read(0x2000004, &cpu), first);
+        assert_eq!(bus.read(0x2000008, &cpu), second);
+        assert_eq!(cpu.get_reg(0), 0xfffffffc);
+        assert_eq!(cpu.get_reg(1), 0x2000000);
+    }
+}
@@ -9,6 +9,7 @@ mod single_data_swap;
 mod single_data_transfer;
 mod swi;
 
+pub(crate) use self::multiply::multiplier_cycles;
 use crate::bus::Bus;
 use crate::cpu::{Cpu, CPSR};
 use crate::utils::AddressableBits;
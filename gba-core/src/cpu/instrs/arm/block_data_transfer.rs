@@ -58,6 +58,8 @@ impl ArmInstruction for Ldm {
                 cpu.set_reg(rn, cpu.get_reg(rn) - 0x40);
             }
         }
+        // The last register is written in an internal cycle.
+        bus.idle(1);
     }
 
     fn disassembly(&self, instruction: u32) -> String {
@@ -331,7 +331,7 @@ struct FlagUpdates {
 }
 
 #[inline]
-fn execute_op<F>(cpu: &mut Cpu, instruction: u32, flag_only: bool, op_closure: F)
+fn execute_op<F>(cpu: &mut Cpu, bus: &mut Bus, instruction: u32, flag_only: bool, op_closure: F)
 where
     // op1, op2, shifter carry
     F: Fn(u32, u32, bool) -> (u32, FlagUpdates),
@@ -344,6 +344,10 @@ where
     } else {
         cpu.get_reg(fields.rn)
     };
+    // Shifting by a register takes an internal cycle to read the extra register.
+    if fields.shifter.takes_extra_cycle() {
+        bus.idle(1);
+    }
 
     let (output, flags) = op_closure(op1, op2, c);
 
@@ -378,8 +382,8 @@ where
 }
 
 impl ArmInstruction for And {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, shift_carry| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, false, |op1, op2, shift_carry| {
             let result = op1 & op2;
             (
                 result,
@@ -400,8 +404,8 @@ impl ArmInstruction for And {
 }
 
 impl ArmInstruction for Eor {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, shift_carry| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, false, |op1, op2, shift_carry| {
             let result = op1 ^ op2;
             (
                 result,
@@ -422,8 +426,8 @@ impl ArmInstruction for Eor {
 }
 
 impl ArmInstruction for Sub {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, _| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, false, |op1, op2, _| {
             let (result, borrow) = op1.overflowing_sub(op2);
             (
                 result,
@@ -444,8 +448,8 @@ impl ArmInstruction for Sub {
 }
 
 impl ArmInstruction for Rsb {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, _| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, false, |op1, op2, _| {
             let (result, borrow) = op2.overflowing_sub(op1);
             (
                 result,
@@ -466,8 +470,8 @@ impl ArmInstruction for Rsb {
 }
 
 impl ArmInstruction for Add {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, _| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, false, |op1, op2, _| {
             let (result, c) = op1.overflowing_add(op2);
             let n = result.bit(31) == 1;
             let z = result == 0;
@@ -491,8 +495,8 @@ impl ArmInstruction for Add {
 }
 
 impl ArmInstruction for Sbc {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, shift_carry| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, false, |op1, op2, shift_carry| {
             let (mut result, mut borrow) = op1.overflowing_sub(op2);
             let mut overflow = op1.bit(31) != op2.bit(31) && op1.bit(31) != result.bit(31);
             if !shift_carry {
@@ -522,8 +526,8 @@ impl ArmInstruction for Sbc {
 }
 
 impl ArmInstruction for Rsc {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op2, op1, shift_carry| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, false, |op2, op1, shift_carry| {
             let (mut result, mut borrow) = op1.overflowing_sub(op2);
             let mut overflow = sub_overflows(op1, op2, result);
             if !shift_carry {
@@ -553,9 +557,9 @@ impl ArmInstruction for Rsc {
 }
 
 impl ArmInstruction for Adc {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
         let c_flag = cpu.get_cpsr_bit(CPSR::C);
-        execute_op(cpu, instruction, false, |op1, op2, _| {
+        execute_op(cpu, bus, instruction, false, |op1, op2, _| {
             let (mut result, mut carry) = op1.overflowing_add(op2);
             let mut overflow = add_overflows(op1, op2, result);
             if c_flag == 1 {
@@ -585,8 +589,8 @@ impl ArmInstruction for Adc {
 }
 
 impl ArmInstruction for Tst {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, true, |op1, op2, shift_carry| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, true, |op1, op2, shift_carry| {
             let result = op1 & op2;
             (
                 result,
@@ -607,8 +611,8 @@ impl ArmInstruction for Tst {
 }
 
 impl ArmInstruction for Teq {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, true, |op1, op2, shift_carry| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, true, |op1, op2, shift_carry| {
             let result = op1 ^ op2;
             (
                 result,
@@ -629,8 +633,8 @@ impl ArmInstruction for Teq {
 }
 
 impl ArmInstruction for Cmp {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, true, |op1, op2, _| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, true, |op1, op2, _| {
             let (result, borrow) = op1.overflowing_sub(op2);
 
             (
@@ -652,8 +656,8 @@ impl ArmInstruction for Cmp {
 }
 
 impl ArmInstruction for Cmn {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, true, |op1, op2, _| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, true, |op1, op2, _| {
             let (result, carry) = op1.overflowing_add(op2);
             (
                 result,
@@ -674,8 +678,8 @@ impl ArmInstruction for Cmn {
 }
 
 impl ArmInstruction for Orr {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, shift_carry| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, false, |op1, op2, shift_carry| {
             let result = op1 | op2;
             let n = result.bit(31) == 1;
             let z = result == 0;
@@ -697,8 +701,8 @@ impl ArmInstruction for Orr {
 }
 
 impl ArmInstruction for Mov {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |_, op2, shift_carry| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, false, |_, op2, shift_carry| {
             (
                 op2,
                 FlagUpdates {
@@ -718,8 +722,8 @@ impl ArmInstruction for Mov {
 }
 
 impl ArmInstruction for Bic {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |op1, op2, shift_carry| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, false, |op1, op2, shift_carry| {
             let result = op1 & !op2;
             (
                 result,
@@ -740,8 +744,8 @@ impl ArmInstruction for Bic {
 }
 
 impl ArmInstruction for Mvn {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
-        execute_op(cpu, instruction, false, |_, op2, shift_carry| {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        execute_op(cpu, bus, instruction, false, |_, op2, shift_carry| {
             let result = !op2;
             (
                 result,
@@ -20,6 +20,7 @@ impl ArmInstruction for LDRH {
         } = AddressingMode::decode_halfword(instruction).address(cpu);
 
         let val = bus.read_half(address, cpu);
+        bus.idle(1);
         cpu.set_reg(rd, val);
 
         if let Some(address) = write_back {
@@ -69,6 +70,7 @@ impl ArmInstruction for LDRSB {
         } = AddressingMode::decode_halfword(instruction).address(cpu);
 
         let val = bus.read_byte(address, cpu);
+        bus.idle(1);
         cpu.set_reg(rd, i32::from(val as i8) as u32);
 
         if let Some(address) = write_back {
@@ -98,6 +100,7 @@ impl ArmInstruction for LDRSH {
         } else {
             i32::from(bus.read_byte(address, cpu) as i8) as u32
         };
+        bus.idle(1);
         cpu.set_reg(rd, val);
 
         if let Some(address) = write_back {
@@ -9,6 +9,18 @@ pub struct Smlal;
 pub struct Umull;
 pub struct Smull;
 
+/// The number of internal cycles the multiplier takes. It works through `rs` 8 bits at a time
+/// and stops early once the remaining bits are all zeros, or all ones for signed multiplies.
+pub(crate) fn multiplier_cycles(rs: u32, signed: bool) -> u32 {
+    let rs = if signed && rs.bit(31) == 1 { !rs } else { rs };
+    match rs {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x1_0000..=0xff_ffff => 3,
+        _ => 4,
+    }
+}
+
 pub fn decode_multiply(instruction: u32) -> Box<dyn ArmInstruction> {
     if instruction.bit(21) == 1 {
         Box::new(Mla)
@@ -29,12 +41,13 @@ pub fn decode_multiply_long(instruction: u32) -> Box<dyn ArmInstruction> {
 }
 
 impl ArmInstruction for Mla {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u32) {
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u32) {
         let s = instruction.bit(20);
         let rd = instruction.bits(16, 19);
         let rn = cpu.get_reg(instruction.bits(12, 15));
         let rs = cpu.get_reg(instruction.bits(8, 11));
         let rm = cpu.get_reg(instruction.bits(0, 3));
+        bus.idle(multiplier_cycles(rs, true) + 1);
 
         let result = rm.wrapping_mul(rs).wrapping_add(rn);
         cpu.set_reg(rd, result);
@@ -62,11 +75,12 @@ impl ArmInstruction for Mla {
 }
 
 impl ArmInstruction for Mul {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u32) {
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u32) {
         let s = instruction.bit(20);
         let rd = instruction.bits(16, 19);
         let rs = cpu.get_reg(instruction.bits(8, 11));
         let rm = cpu.get_reg(instruction.bits(0, 3));
+        bus.idle(multiplier_cycles(rs, true));
 
         let result = rm.wrapping_mul(rs);
         cpu.set_reg(rd, result);
@@ -93,12 +107,13 @@ impl ArmInstruction for Mul {
 }
 
 impl ArmInstruction for Umlal {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u32) {
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u32) {
         let s = instruction.bit(20);
         let rd_hi = instruction.bits(16, 19);
         let rd_lo = instruction.bits(12, 15);
         let rs = cpu.get_reg(instruction.bits(8, 11));
         let rm = cpu.get_reg(instruction.bits(0, 3));
+        bus.idle(multiplier_cycles(rs, false) + 2);
 
         let wide_result: u64 = (rs as u64) * (rm as u64);
         let wide_result_lo: u32 = wide_result.bits(0, 31).try_into().unwrap();
@@ -138,12 +153,13 @@ impl ArmInstruction for Umlal {
 }
 
 impl ArmInstruction for Smlal {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u32) {
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u32) {
         let s = instruction.bit(20);
         let rd_hi = instruction.bits(16, 19);
         let rd_lo = instruction.bits(12, 15);
         let rs = cpu.get_reg(instruction.bits(8, 11)) as i32;
         let rm = cpu.get_reg(instruction.bits(0, 3)) as i32;
+        bus.idle(multiplier_cycles(rs as u32, true) + 2);
 
         let wide_result: u64 = (i64::from(rs) * i64::from(rm)) as u64;
         let wide_result_lo: u32 = wide_result.bits(0, 31).try_into().unwrap();
@@ -183,12 +199,13 @@ impl ArmInstruction for Smlal {
 }
 
 impl ArmInstruction for Umull {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u32) {
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u32) {
         let s = instruction.bit(20);
         let rd_hi = instruction.bits(16, 19);
         let rd_lo = instruction.bits(12, 15);
         let rs = cpu.get_reg(instruction.bits(8, 11));
         let rm = cpu.get_reg(instruction.bits(0, 3));
+        bus.idle(multiplier_cycles(rs, false) + 1);
 
         let wide_result: u64 = (rs as u64) * (rm as u64);
         let low: u32 = wide_result.bits(0, 31).try_into().unwrap();
@@ -222,12 +239,13 @@ impl ArmInstruction for Umull {
 }
 
 impl ArmInstruction for Smull {
-    fn execute(&self, cpu: &mut crate::cpu::Cpu, _: &mut crate::bus::Bus, instruction: u32) {
+    fn execute(&self, cpu: &mut crate::cpu::Cpu, bus: &mut crate::bus::Bus, instruction: u32) {
         let s = instruction.bit(20);
         let rd_hi = instruction.bits(16, 19);
         let rd_lo = instruction.bits(12, 15);
         let rs = cpu.get_reg(instruction.bits(8, 11)) as i32;
         let rm = cpu.get_reg(instruction.bits(0, 3)) as i32;
+        bus.idle(multiplier_cycles(rs as u32, true) + 1);
 
         let wide_result: u64 = (i64::from(rs) * i64::from(rm)) as u64;
         let low: u32 = wide_result.bits(0, 31).try_into().unwrap();
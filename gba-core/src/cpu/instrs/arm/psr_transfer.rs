@@ -58,7 +58,7 @@ impl ArmInstruction for MSR {
     fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u32) {
         let unalloc_mask = 0x0fffff00;
         let user_mask = 0xf0000000;
-        let priv_mask = 0x0000000f;
+        let priv_mask = 0x000000df;
         let state_mask = 0x00000020;
 
         let fields = MsrFields::parse(instruction, cpu);
@@ -78,17 +78,14 @@ impl ArmInstruction for MSR {
             });
 
         if !fields.r {
-            let mask;
             println!("{:?}", cpu.get_mode());
-            if cpu.in_privileged_mode() {
-                if (fields.operand & state_mask) != 0 {
-                    todo!("unpredictable");
-                } else {
-                    mask = byte_mask & (user_mask | priv_mask);
-                }
+            let mask = if cpu.in_privileged_mode() {
+                // Privileged modes can change the I and F bits and the mode, but the T bit can
+                // only be changed by branching.
+                byte_mask & (user_mask | priv_mask)
             } else {
-                mask = byte_mask & user_mask;
-            }
+                byte_mask & user_mask
+            };
             cpu.regs.cpsr = (cpu.regs.cpsr & !mask) | (fields.operand & mask) | 0x10;
         } else {
             if cpu.mode_has_spsr() {
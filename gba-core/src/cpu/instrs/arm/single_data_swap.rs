@@ -14,6 +14,7 @@ impl ArmInstruction for Swp {
         let address = cpu.get_reg(rn);
         let temp = bus.read(address, cpu);
         bus.write(address, cpu.get_reg(rm));
+        bus.idle(1);
         cpu.set_reg(rd, temp);
     }
 
@@ -35,6 +36,7 @@ impl ArmInstruction for Swpb {
         let address = cpu.get_reg(rn);
         let temp = bus.read_byte(address, cpu);
         bus.write_byte(address, cpu.get_reg(rm) as u8);
+        bus.idle(1);
         cpu.set_reg(rd, temp.into());
     }
 
@@ -355,6 +355,8 @@ impl ArmInstruction for LDR {
         } else {
             bus.read_byte(address, cpu) as u32
         };
+        // Loads take an internal cycle to write the value to the register.
+        bus.idle(1);
 
         cpu.set_reg(rd, val);
         if let Some(address) = write_back {
@@ -6,7 +6,11 @@ use crate::utils::AddressableBits;
 pub struct Swi;
 
 impl ArmInstruction for Swi {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, _: u32) {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u32) {
+        if cpu.hle_bios && cpu.hle_swi(bus, instruction.bits(16, 23)) {
+            return;
+        }
+
         cpu.set_reg_with_mode(14, Mode::Supervisor, cpu.get_reg(15) - 4);
         *cpu.regs.spsr_mut(&Mode::Supervisor) = cpu.regs.cpsr;
         cpu.regs.cpsr = cpu.regs.cpsr.bits(6, 31) | 0b010011;
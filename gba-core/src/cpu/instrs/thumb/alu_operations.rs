@@ -1,6 +1,6 @@
 use crate::{
     bus::Bus,
-    cpu::{Cpu, CPSR},
+    cpu::{instrs::arm::multiplier_cycles, Cpu, CPSR},
     utils::{add_overflows, sub_overflows, AddressableBits},
 };
 
@@ -66,8 +66,8 @@ pub fn decode(instruction: u16) -> Box<dyn ThumbInstruction> {
 macro_rules! alu_thumb_instr_impl {
     ($SelfT:ty, $Op:literal, $Closure:expr) => {
         impl ThumbInstruction for $SelfT {
-            fn execute(&self, cpu: &mut Cpu, _: &mut Bus, instruction: u16) {
-                execute_op(cpu, instruction, $Closure);
+            fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
+                execute_op(cpu, bus, instruction, $Closure);
             }
 
             fn disassembly(&self, instruction: u16) -> String {
@@ -283,7 +283,7 @@ alu_thumb_instr_impl!(MVN, "MVN", |_, op2, _| {
 });
 
 #[inline]
-fn execute_op<F>(cpu: &mut Cpu, instruction: u16, op_closure: F)
+fn execute_op<F>(cpu: &mut Cpu, bus: &mut Bus, instruction: u16, op_closure: F)
 where
     // op1, op2, c_flag -> result, flag_updates
     F: Fn(u32, u32, u32) -> (Option<u32>, FlagUpdates),
@@ -294,6 +294,14 @@ where
     let op1 = cpu.get_reg(rd.into());
     let op2 = cpu.get_reg(rs.into());
 
+    match instruction.bits(6, 9) {
+        // Shifts by a register take an internal cycle.
+        0b0010 | 0b0011 | 0b0100 | 0b0111 => bus.idle(1),
+        // MUL, where rd is the operand the multiplier works through.
+        0b1101 => bus.idle(multiplier_cycles(op1, true)),
+        _ => {}
+    }
+
     let (result, flags) = op_closure(op1, op2, cpu.get_cpsr_bit(CPSR::C));
 
     if let Some(b) = flags.n {
@@ -44,6 +44,7 @@ impl ThumbInstruction for Ldrh {
 
         // How does misalignment work here?
         let data = bus.read_half(address, cpu);
+        bus.idle(1);
 
         cpu.set_reg(rd.into(), data);
     }
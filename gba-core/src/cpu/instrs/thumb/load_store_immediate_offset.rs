@@ -69,6 +69,7 @@ impl ThumbInstruction for Ldr {
 
         // How does misalignment work here?
         let data = bus.read(address, cpu);
+        bus.idle(1);
 
         cpu.set_reg(rd.into(), data);
     }
@@ -91,6 +92,7 @@ impl ThumbInstruction for Ldrb {
         let address = cpu.get_reg(rn.into()) + offset as u32;
 
         let data = bus.read_byte(address, cpu);
+        bus.idle(1);
 
         cpu.set_reg(rd.into(), data.into());
     }
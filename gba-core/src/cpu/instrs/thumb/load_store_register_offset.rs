@@ -62,6 +62,7 @@ impl ThumbInstruction for Ldr {
 
         let address = cpu.get_reg(rn.into()).wrapping_add(cpu.get_reg(rm.into()));
         let data = bus.read(address, cpu);
+        bus.idle(1);
         cpu.set_reg(rd.into(), data);
     }
 
@@ -81,6 +82,7 @@ impl ThumbInstruction for Ldrb {
 
         let address = cpu.get_reg(rn.into()).wrapping_add(cpu.get_reg(rm.into()));
         let data = bus.read_byte(address, cpu);
+        bus.idle(1);
         cpu.set_reg(rd.into(), data.into());
     }
 
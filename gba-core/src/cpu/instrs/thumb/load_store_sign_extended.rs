@@ -44,6 +44,7 @@ impl ThumbInstruction for Ldrh {
 
         let address = cpu.get_reg(rn.into()).wrapping_add(cpu.get_reg(rm.into()));
         let data = bus.read_half(address, cpu);
+        bus.idle(1);
         cpu.set_reg(rd.into(), data.into());
     }
 
@@ -63,6 +64,7 @@ impl ThumbInstruction for Ldrsb {
 
         let address = cpu.get_reg(rn.into()).wrapping_add(cpu.get_reg(rm.into()));
         let data = i32::from(bus.read_byte(address, cpu) as i8) as u32;
+        bus.idle(1);
         cpu.set_reg(rd.into(), data);
     }
 
@@ -88,6 +90,7 @@ impl ThumbInstruction for Ldrsh {
         } else {
             i32::from(bus.read_byte(address, cpu) as i8) as u32
         };
+        bus.idle(1);
         cpu.set_reg(rd.into(), data);
     }
 
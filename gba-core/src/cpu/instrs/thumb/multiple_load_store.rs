@@ -73,6 +73,7 @@ impl ThumbInstruction for Ldmia {
             cpu.flush_pipeline();
             cpu.set_reg(rn.into(), cpu.get_reg(rn.into()) + 0x40);
         }
+        bus.idle(1);
     }
 
     fn disassembly(&self, instruction: u16) -> String {
@@ -11,6 +11,7 @@ impl ThumbInstruction for LdrPc {
 
         let address = (cpu.get_reg(15) & 0xffff_fffc) + imm as u32 * 4;
         let value = bus.read(address, cpu);
+        bus.idle(1);
         cpu.set_reg(rd.into(), value);
     }
 
@@ -59,6 +59,7 @@ impl ThumbInstruction for Pop {
             cpu.flush_pipeline();
             address += 4;
         }
+        bus.idle(1);
 
         cpu.set_reg(13, address);
     }
@@ -37,6 +37,7 @@ impl ThumbInstruction for Ldr {
 
         let address = cpu.get_reg(13).wrapping_add(imm * 4);
         let data = bus.read(address, cpu);
+        bus.idle(1);
         cpu.set_reg(rd, data);
     }
 
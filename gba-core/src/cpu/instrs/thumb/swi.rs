@@ -7,7 +7,11 @@ use super::ThumbInstruction;
 pub struct Swi;
 
 impl ThumbInstruction for Swi {
-    fn execute(&self, cpu: &mut Cpu, _: &mut Bus, _: u16) {
+    fn execute(&self, cpu: &mut Cpu, bus: &mut Bus, instruction: u16) {
+        if cpu.hle_bios && cpu.hle_swi(bus, u32::from(instruction.bits(0, 7))) {
+            return;
+        }
+
         cpu.set_reg_with_mode(14, Mode::Supervisor, cpu.get_reg(15) - 2);
         *cpu.regs.spsr_mut(&Mode::Supervisor) = cpu.regs.cpsr;
         cpu.regs.cpsr = cpu.regs.cpsr.bits(6, 31) | 0b010011;
@@ -1,3 +1,4 @@
+mod hle;
 mod instrs;
 mod regs;
 
@@ -7,7 +8,7 @@ use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::wasm_bindgen;
 use wasm_bindgen::JsValue;
 
-use crate::bus::Bus;
+use crate::bus::{Bus, PowerState};
 use crate::utils::AddressableBits;
 
 pub use self::instrs::arm::ArmInstruction;
@@ -82,7 +83,12 @@ pub struct Cpu {
     instr_pipeline: [u32; 2],
     instr_pipeline_size: usize,
     cycle: u128,
-    old_interrupt: bool,
+    // Cycles left before the next instruction, spent waiting on the bus.
+    wait_cycles: u32,
+    // Run supported BIOS functions natively instead of through the BIOS.
+    pub(crate) hle_bios: bool,
+    // Set while an HLE IntrWait is waiting to be executed again.
+    hle_intr_waiting: bool,
 
     pc_history: VecDeque<u32>,
 }
@@ -96,12 +102,16 @@ impl Default for Cpu {
             instr_pipeline_size: 0,
 
             cycle: 0,
-
-            old_interrupt: false,
+            wait_cycles: 0,
+            hle_bios: false,
+            hle_intr_waiting: false,
 
             pc_history: VecDeque::new(),
         };
-        cpu.set_mode(Mode::User);
+        // The CPU starts in supervisor mode with interrupts disabled, at the BIOS reset vector.
+        cpu.set_mode(Mode::Supervisor);
+        cpu.regs.cpsr.mut_bit(6, true);
+        cpu.regs.cpsr.mut_bit(7, true);
         cpu
     }
 }
@@ -186,11 +196,12 @@ impl Cpu {
         self.regs.get(idx, &self.get_mode())
     }
 
-    /// Only correct outside of .tick() calls
+    /// Only correct outside of .tick() calls. Wraps around while the pipeline is filling from
+    /// the reset vector.
     pub fn get_executing_instruction_pc(&self) -> u32 {
         match self.get_state() {
-            State::ARM => self.get_reg_internal(15) - 8,
-            State::Thumb => self.get_reg_internal(15) - 4,
+            State::ARM => self.get_reg_internal(15).wrapping_sub(8),
+            State::Thumb => self.get_reg_internal(15).wrapping_sub(4),
         }
     }
 
@@ -233,11 +244,38 @@ impl Cpu {
         *self.regs.get_mut(13, &Mode::IRQ) = 0x3007fa0;
         *self.regs.get_mut(13, &Mode::Supervisor) = 0x3007fe0;
         *self.regs.get_mut(15, &Mode::User) = 0x8000000;
-        self.regs.cpsr = 0xdf;
+        // System mode with IRQs enabled, as left by the BIOS.
+        self.regs.cpsr = 0x1f;
         //self.mode = Mode::System;
     }
 
     pub fn tick(&mut self, bus: &mut Bus, arm_lut: &ArmLut, thumb_lut: &ThumbLut) {
+        // Each tick is one cycle, so the last instruction's bus accesses are paid for here.
+        if self.wait_cycles > 0 {
+            self.wait_cycles -= 1;
+            return;
+        }
+
+        // Nothing is executed while halted or stopped.
+        if bus.io_map.power_state() != PowerState::Running {
+            self.idle(bus, 1);
+            return;
+        }
+
+        self.wait_cycles = self.step(bus, arm_lut, thumb_lut) - 1;
+    }
+
+    /// Spend some cycles halted or stopped.
+    pub fn idle(&mut self, bus: &Bus, cycles: u32) {
+        bus.take_access_cycles();
+        self.cycle += u128::from(cycles);
+    }
+
+    /// Run one pipeline stage and return the number of cycles it took, including any still left
+    /// over from `tick`.
+    pub fn step(&mut self, bus: &mut Bus, arm_lut: &ArmLut, thumb_lut: &ThumbLut) -> u32 {
+        let waited = std::mem::take(&mut self.wait_cycles);
+
         if self.instr_pipeline_size == 2 {
             self.pc_history
                 .push_front(self.get_executing_instruction_pc());
@@ -246,22 +284,17 @@ impl Cpu {
             }
         }
 
-        // TODO: check if i can read these directly
-        let ime_flag = bus.read_byte(0x4000208, self);
-        let ie_flag = bus.read_half(0x4000200, self);
-        let if_flag = bus.read_half(0x4000202, self);
-
-        let new_interrupt = ime_flag.bit(0) == 1 && ie_flag & if_flag != 0;
-
-        if !self.old_interrupt && new_interrupt {
+        // IRQs are level triggered and masked by the CPSR I bit. They're only taken between
+        // instructions, once the pipeline is full and the return address is known.
+        let irq_disabled = self.regs.cpsr.bit(7) == 1;
+        if self.instr_pipeline_size == 2 && !irq_disabled && bus.io_map.irq_line() {
             self.handle_interrupt();
         }
-        self.old_interrupt = new_interrupt;
 
         let instruction = self.instr_pipeline[0];
 
         self.instr_pipeline[0] = self.instr_pipeline[1];
-        self.instr_pipeline[1] = bus.read(self.regs.pc(), self);
+        self.instr_pipeline[1] = bus.fetch(self.regs.pc(), self);
 
         match self.get_state() {
             State::ARM => *self.regs.pc_mut() += 4,
@@ -274,7 +307,9 @@ impl Cpu {
             self.instr_pipeline_size += 1;
         }
 
-        self.cycle += 1;
+        let cycles = bus.take_access_cycles().max(1);
+        self.cycle += u128::from(cycles);
+        waited + cycles
     }
 
     pub fn in_privileged_mode(&self) -> bool {
@@ -303,10 +338,36 @@ impl Cpu {
         }
     }
 
-    pub fn prefetched_instruction(&self) -> u32 {
+    /// Whether the BIOS is readable, which is decided by the address being fetched from rather
+    /// than the one being executed.
+    pub fn executing_bios(&self) -> bool {
+        self.regs.pc() < 0x4000
+    }
+
+    /// The value left on the bus by the last opcode fetch, which is what reads from unmapped
+    /// memory return. Only correct while an instruction is executing, when the pipeline holds
+    /// the opcodes at $+4 and $+8 in ARM state or $+2 and $+4 in Thumb state.
+    pub fn prefetched_instruction(&self, bus: &Bus) -> u32 {
         match self.get_state() {
-            State::ARM => self.instr_pipeline[0],
-            State::Thumb => 0, // TODO: implement this properly
+            State::ARM => self.instr_pipeline[1],
+            State::Thumb => {
+                // Thumb fetches are halfwords, so the upper halfword depends on the region.
+                let fetch_address = self.regs.pc().wrapping_sub(2);
+                let current = self.instr_pipeline[1] & 0xffff;
+                let previous = self.instr_pipeline[0] & 0xffff;
+                let aligned = fetch_address.bit(1) == 0;
+                let (low, high) = match fetch_address >> 24 {
+                    // BIOS and OAM
+                    0x00 | 0x07 if aligned => {
+                        (current, bus.peek_half(fetch_address.wrapping_add(2), self))
+                    }
+                    // IWRAM
+                    0x03 if aligned => (current, previous),
+                    0x00 | 0x03 | 0x07 => (previous, current),
+                    _ => (current, current),
+                };
+                low | (high << 16)
+            }
         }
     }
 }
@@ -345,7 +406,7 @@ mod tests {
         let mut cpu = Cpu::default();
         let mut bus = Bus::default();
         let (arm_lut, thumb_lut) = generate_luts();
-        bus.set_bios(include_bytes!("../../bios.bin"));
+        bus.set_bios(include_bytes!("../../bios.bin")).unwrap();
 
         cpu.skip_bios();
         cpu.set_reg_with_mode(0, Mode::System, r0);
@@ -371,6 +432,324 @@ mod tests {
         assert_eq!(result3, expected3, "cult abs div {} by {}", r0, r1);
     }
 
+    /// Call a BIOS function with a real SWI, running the bundled BIOS until it returns.
+    fn bios_swi(bus: &mut Bus, function: u32, args: &[u32]) -> Cpu {
+        let mut cpu = Cpu::default();
+        let (arm_lut, thumb_lut) = generate_luts();
+
+        // swi, then loop forever.
+        bus.write(0x3000000, 0xef000000 | function << 16);
+        bus.write(0x3000004, 0xeafffffe);
+        cpu.skip_bios();
+        for (reg, &value) in args.iter().enumerate() {
+            cpu.set_reg_with_mode(reg as u32, Mode::System, value);
+        }
+        cpu.set_reg(15, 0x3000000);
+        cpu.flush_pipeline();
+        for _ in 0..1_000_000 {
+            if cpu.get_executing_instruction_pc() == 0x3000004 {
+                break;
+            }
+            cpu.tick(bus, &arm_lut, &thumb_lut);
+        }
+        assert_eq!(cpu.get_executing_instruction_pc(), 0x3000004);
+        cpu
+    }
+
+    /// Call a BIOS function through the HLE BIOS.
+    fn hle_swi(bus: &mut Bus, function: u32, args: &[u32]) -> Cpu {
+        let mut cpu = Cpu::default();
+        cpu.skip_bios();
+        for (reg, &value) in args.iter().enumerate() {
+            cpu.set_reg_with_mode(reg as u32, Mode::System, value);
+        }
+        assert!(cpu.hle_swi(bus, function));
+        cpu
+    }
+
+    /// Run Div through the HLE BIOS, checking it against the bundled BIOS.
+    fn test_hle_div(r0: u32, r1: u32) {
+        let mut bus = Bus::default();
+        let bios_cpu = bios_swi(&mut bus, 0x06, &[r0, r1]);
+        let hle_cpu = hle_swi(&mut bus, 0x06, &[r0, r1]);
+
+        for reg in [0, 1, 3] {
+            assert_eq!(
+                hle_cpu.get_reg(reg),
+                bios_cpu.get_reg(reg),
+                "r{} of hle div {} by {}",
+                reg,
+                r0,
+                r1
+            );
+        }
+    }
+
+    /// Tick until the CPU has finished waiting for the bus, so each call runs one pipeline stage.
+    fn step(cpu: &mut Cpu, bus: &mut Bus, arm_lut: &ArmLut, thumb_lut: &ThumbLut) {
+        cpu.tick(bus, arm_lut, thumb_lut);
+        while cpu.wait_cycles > 0 {
+            cpu.tick(bus, arm_lut, thumb_lut);
+        }
+    }
+
+    /// Run the Thumb program at `address` until `steps` instructions have executed.
+    fn run_thumb(bus: &mut Bus, address: u32, program: &[u16], steps: usize) -> Cpu {
+        let mut cpu = Cpu::default();
+        let (arm_lut, thumb_lut) = generate_luts();
+        for (i, &halfword) in program.iter().enumerate() {
+            bus.write_half(address + 2 * i as u32, halfword);
+        }
+
+        cpu.skip_bios();
+        cpu.set_flag(CPSR::T, true);
+        *cpu.regs.pc_mut() = address;
+        for _ in 0..steps + 2 {
+            step(&mut cpu, bus, &arm_lut, &thumb_lut);
+        }
+        cpu
+    }
+
+    // movs r1, #1; lsls r1, r1, #28; ldr r0, [r1]; then the halfwords at $+2 and $+4.
+    const THUMB_OPEN_BUS_READ: [u16; 5] = [0x2101, 0x0709, 0x6808, 0x1234, 0xabcd];
+
+    #[test]
+    fn arm_open_bus_reads_opcode_at_pc_plus_8() {
+        let mut bus = Bus::default();
+        let (arm_lut, thumb_lut) = generate_luts();
+        let mut rom = Vec::new();
+        // mov r1, #0x10000000; ldr r0, [r1]; nop; then the opcode which is on the bus.
+        for word in [0xe3a01201u32, 0xe5910000, 0xe1a00000, 0x12345678] {
+            rom.extend_from_slice(&word.to_le_bytes());
+        }
+        bus.load_rom(&rom).unwrap();
+
+        let mut cpu = Cpu::default();
+        cpu.skip_bios();
+        for _ in 0..4 {
+            step(&mut cpu, &mut bus, &arm_lut, &thumb_lut);
+        }
+        assert_eq!(cpu.get_reg(0), 0x12345678);
+    }
+
+    #[test]
+    fn thumb_open_bus_duplicates_opcode_in_rom() {
+        let mut bus = Bus::default();
+        let mut rom = Vec::new();
+        for halfword in THUMB_OPEN_BUS_READ {
+            rom.extend_from_slice(&halfword.to_le_bytes());
+        }
+        bus.load_rom(&rom).unwrap();
+
+        let mut cpu = Cpu::default();
+        let (arm_lut, thumb_lut) = generate_luts();
+        cpu.skip_bios();
+        cpu.set_flag(CPSR::T, true);
+        for _ in 0..5 {
+            step(&mut cpu, &mut bus, &arm_lut, &thumb_lut);
+        }
+        assert_eq!(cpu.get_reg(0), 0xabcd_abcd);
+    }
+
+    #[test]
+    fn instructions_wait_for_rom() {
+        let mut bus = Bus::default();
+        let (arm_lut, thumb_lut) = generate_luts();
+        // A run of ARM nops, fetched with sequential 32 bit accesses.
+        bus.load_rom(&[0x00, 0x00, 0xa0, 0xe1].repeat(16)).unwrap();
+
+        let mut cpu = Cpu::default();
+        cpu.skip_bios();
+        for _ in 0..3 {
+            step(&mut cpu, &mut bus, &arm_lut, &thumb_lut);
+        }
+        let start = cpu.cycle;
+        step(&mut cpu, &mut bus, &arm_lut, &thumb_lut);
+        // Two halfwords with 2 wait states each by default.
+        assert_eq!(cpu.cycle - start, 6);
+
+        // WS0 with 1 wait state for sequential accesses.
+        bus.io_map.write::<u16, 2>(0x4000204, 0x0014);
+        let start = cpu.cycle;
+        step(&mut cpu, &mut bus, &arm_lut, &thumb_lut);
+        assert_eq!(cpu.cycle - start, 4);
+    }
+
+    #[test]
+    fn thumb_open_bus_in_iwram_depends_on_alignment() {
+        let mut bus = Bus::default();
+        // The load is at 0x3000004, so $+4 is word aligned and $+2 is the high halfword.
+        let cpu = run_thumb(&mut bus, 0x3000000, &THUMB_OPEN_BUS_READ, 3);
+        assert_eq!(cpu.get_reg(0), 0x1234_abcd);
+
+        let mut bus = Bus::default();
+        let mut program = vec![0x46c0];
+        program.extend_from_slice(&THUMB_OPEN_BUS_READ);
+        // With a nop first, $+2 is the low halfword instead.
+        let cpu = run_thumb(&mut bus, 0x3000000, &program, 4);
+        assert_eq!(cpu.get_reg(0), 0xabcd_1234);
+    }
+
+    /// Run each ARM instruction from IWRAM, where every access takes one cycle, and return the
+    /// cycles taken by each.
+    fn arm_cycles(program: &[u32]) -> Vec<u128> {
+        let mut bus = Bus::default();
+        let (arm_lut, thumb_lut) = generate_luts();
+        for (i, &word) in program.iter().enumerate() {
+            bus.write(0x3000000 + 4 * i as u32, word);
+        }
+
+        let mut cpu = Cpu::default();
+        cpu.skip_bios();
+        *cpu.regs.pc_mut() = 0x3000000;
+        for _ in 0..2 {
+            step(&mut cpu, &mut bus, &arm_lut, &thumb_lut);
+        }
+        (0..program.len())
+            .map(|_| {
+                let start = cpu.cycle;
+                step(&mut cpu, &mut bus, &arm_lut, &thumb_lut);
+                cpu.cycle - start
+            })
+            .collect()
+    }
+
+    #[test]
+    fn privileged_msr_sets_interrupt_flags_but_not_thumb() {
+        let mut bus = Bus::default();
+        let (arm_lut, thumb_lut) = generate_luts();
+        // msr cpsr_c, #0xdf; msr cpsr_c, #0x3f
+        bus.write(0x3000000, 0xe321f0df);
+        bus.write(0x3000004, 0xe321f03f);
+
+        let mut cpu = Cpu::default();
+        cpu.skip_bios();
+        *cpu.regs.pc_mut() = 0x3000000;
+        for _ in 0..3 {
+            step(&mut cpu, &mut bus, &arm_lut, &thumb_lut);
+        }
+        assert_eq!(cpu.regs.cpsr & 0xff, 0xdf);
+
+        step(&mut cpu, &mut bus, &arm_lut, &thumb_lut);
+        assert_eq!(cpu.regs.cpsr & 0xff, 0x1f);
+    }
+
+    #[test]
+    fn internal_cycles() {
+        let cycles = arm_cycles(&[
+            // mov r1, #0x100; mov r2, #-1
+            0xe3a01c01, 0xe3e02000,
+            // mul r0, r1, r1; mul r0, r1, r2; umull r0, r3, r1, r2; mla r0, r1, r1, r1
+            0xe0000191, 0xe0000291, 0xe0830291, 0xe0201191,
+            // mov r0, r1, lsl r1; ldr r0, [r1]; str r0, [r1]; ldmia r1, {r0, r3}
+            0xe1a00111, 0xe5910000, 0xe5810000, 0xe8910009,
+        ]);
+        assert_eq!(cycles, [1, 1, 3, 2, 6, 4, 2, 3, 2, 4]);
+    }
+
+    #[test]
+    fn branches_refill_the_pipeline() {
+        let mut bus = Bus::default();
+        let (arm_lut, thumb_lut) = generate_luts();
+        // b 0x8000010, followed by nops.
+        let mut rom = 0xea000002u32.to_le_bytes().to_vec();
+        rom.extend([0x00, 0x00, 0xa0, 0xe1].repeat(8));
+        bus.load_rom(&rom).unwrap();
+
+        let mut cpu = Cpu::default();
+        cpu.skip_bios();
+        for _ in 0..2 {
+            step(&mut cpu, &mut bus, &arm_lut, &thumb_lut);
+        }
+        let start = cpu.cycle;
+        // The branch, then fetching the opcodes at the target and the one after it.
+        for _ in 0..3 {
+            step(&mut cpu, &mut bus, &arm_lut, &thumb_lut);
+        }
+        assert_eq!(cpu.get_executing_instruction_pc(), 0x8000010);
+        // S + N + S with the default wait states.
+        assert_eq!(cpu.cycle - start, 6 + 8 + 6);
+    }
+
+    #[test]
+    fn test_hle_affine_sets_match_bios() {
+        let mut bus = Bus::default();
+        // A fixed xorshift sequence, so the inputs cover every sign and angle.
+        let mut state: u32 = 0x1234_5678;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        for _ in 0..200 {
+            let source: Vec<u32> = (0..5).map(|_| random()).collect();
+            for (i, &word) in source.iter().enumerate() {
+                bus.write(0x2000000 + 4 * i as u32, word);
+            }
+
+            // BgAffineSet writes PA-PD and the reference point.
+            bios_swi(&mut bus, 0x0e, &[0x2000000, 0x2001000, 1]);
+            hle_swi(&mut bus, 0x0e, &[0x2000000, 0x2002000, 1]);
+            // ObjAffineSet with the parameters spread out as in OAM.
+            bios_swi(&mut bus, 0x0f, &[0x2000000, 0x2001010, 1, 8]);
+            hle_swi(&mut bus, 0x0f, &[0x2000000, 0x2002010, 1, 8]);
+
+            let cpu = Cpu::default();
+            for offset in (0..0x30).step_by(4) {
+                assert_eq!(
+                    bus.read(0x2002000 + offset, &cpu),
+                    bus.read(0x2001000 + offset, &cpu),
+                    "offset {:#x} for {:08x?}",
+                    offset,
+                    source
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_hle_arc_tan_matches_bios() {
+        let mut bus = Bus::default();
+        let mut state: u32 = 0x8765_4321;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        let mut cases: Vec<(u32, u32)> = (0..300).map(|_| (random(), random())).collect();
+        // Small values like the ones games pass, which are 1.14 fixed point.
+        cases.extend((0..300).map(|_| (random() as i16 as u32, random() as i16 as u32)));
+        // i32::MIN is left out, as the BIOS Div returns nonsense when dividing by it.
+        let edges = [0x8000_0001, 0x7fff_ffff, 0xffff_ffff, 1, 0x4000_0000, 0xc000_0000];
+        cases.extend(edges.iter().flat_map(|&x| edges.map(|y| (x, y))));
+
+        for (r0, r1) in cases {
+            for function in [0x09, 0x0a] {
+                let bios_cpu = bios_swi(&mut bus, function, &[r0, r1]);
+                let hle_cpu = hle_swi(&mut bus, function, &[r0, r1]);
+                assert_eq!(
+                    hle_cpu.get_reg(0),
+                    bios_cpu.get_reg(0),
+                    "swi {:#x} with {:#x}, {:#x}",
+                    function,
+                    r0,
+                    r1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_hle_div_matches_bios() {
+        test_hle_div(1, 1);
+        test_hle_div(123, 10);
+        test_hle_div(0xa000000, 0x10);
+        test_hle_div(-123i32 as u32, 10);
+    }
+
     #[test]
     fn test_div_1_by_1() {
         test_div(1, 1);
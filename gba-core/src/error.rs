@@ -0,0 +1,52 @@
+use std::fmt;
+
+use crate::bus::{BackupError, BiosError};
+
+/// Everything that can go wrong when loading files into the emulator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    EmptyRom,
+    /// ROMs can't be larger than the 32 MiB game pak address space.
+    RomTooLarge(usize),
+    Bios(BiosError),
+    Backup(BackupError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyRom => write!(f, "ROM is empty"),
+            Self::RomTooLarge(size) => write!(
+                f,
+                "ROM is {} bytes, but the largest possible ROM is 32 MiB",
+                size
+            ),
+            Self::Bios(err) => write!(f, "invalid BIOS: {}", err),
+            Self::Backup(err) => write!(f, "invalid save: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Bios(err) => Some(err),
+            Self::Backup(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<BiosError> for Error {
+    fn from(err: BiosError) -> Self {
+        Self::Bios(err)
+    }
+}
+
+impl From<BackupError> for Error {
+    fn from(err: BackupError) -> Self {
+        Self::Backup(err)
+    }
+}
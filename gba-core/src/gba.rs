@@ -1,9 +1,10 @@
 use std::collections::HashSet;
 
-use crate::bus::{self, Bus};
+use crate::bus::{self, BackupType, BiosInfo, Bus, PowerState, RomHeader};
 use crate::cpu::generate_luts;
 use crate::cpu::State;
 use crate::cpu::{ArmInstruction, Cpu, ThumbInstruction};
+use crate::scheduler::Event;
 
 use wasm_bindgen::prelude::*;
 
@@ -49,6 +50,103 @@ impl GbaCore {
     pub fn screen(&self) -> Vec<u8> {
         self.bus.ppu.screen()
     }
+
+    /// Replace the embedded BIOS with the given image, which must be exactly 16 KiB. Returns the
+    /// checksum and identity of the new BIOS.
+    ///
+    /// The core starts at the BIOS reset vector, so unless `skip_bios` is called the BIOS boot
+    /// intro runs before the game.
+    pub fn load_bios(&mut self, bytes: &[u8]) -> crate::Result<BiosInfo> {
+        self.bus.set_bios(bytes)?;
+        Ok(self.bus.bios_info())
+    }
+
+    pub fn bios_info(&self) -> BiosInfo {
+        self.bus.bios_info()
+    }
+
+    /// Force the type of save memory instead of detecting it from the ROM, for this ROM and any
+    /// loaded later. `None` goes back to detection. Any existing save is replaced with an erased
+    /// chip.
+    pub fn set_backup_override(&mut self, backup_type: Option<BackupType>) {
+        self.bus.set_backup_override(backup_type);
+    }
+
+    pub fn backup_type(&self) -> BackupType {
+        self.bus.backup_type()
+    }
+
+    /// Return the raw contents of the save memory, in the layout used by .sav files.
+    pub fn export_save(&self) -> Vec<u8> {
+        self.bus.backup_data().to_vec()
+    }
+
+    /// Load a raw .sav file into the save memory. Empty files are accepted and leave the memory
+    /// erased, otherwise the size must match the save type.
+    pub fn import_save(&mut self, bytes: &[u8]) -> crate::Result<()> {
+        Ok(self.bus.load_backup_data(bytes)?)
+    }
+
+    /// Load a ROM into the game pak. ROMs must be between 1 byte and 32 MiB.
+    pub fn load_rom(&mut self, bytes: &[u8]) -> crate::Result<()> {
+        self.bus.load_rom(bytes)
+    }
+
+    /// Handle every event which is due.
+    fn handle_events(&mut self) {
+        while let Some((event, time)) = self.bus.io_map.scheduler.pop_due() {
+            match event {
+                Event::HBlank => self.bus.ppu.hblank(&mut self.bus.io_map, time),
+                Event::HDraw => self.bus.ppu.hdraw(&mut self.bus.io_map, time),
+                Event::TimerOverflow(timer) => {
+                    self.bus.io_map.handle_timer_overflow(timer, time)
+                }
+                Event::Dma => self.bus.run_dma(&self.cpu),
+                Event::Irq => self.bus.io_map.handle_irq_event(),
+            }
+        }
+    }
+
+    /// Run the CPU until the next event is due, for at most `limit` cycles. Returns the number
+    /// of cycles that passed, which is 0 if a breakpoint was hit straight away.
+    fn run_until_event(&mut self, limit: u64) -> u64 {
+        let scheduler = &self.bus.io_map.scheduler;
+        let start = scheduler.now();
+        let end = scheduler.next_event_time().min(start + limit);
+
+        match self.bus.io_map.power_state() {
+            PowerState::Running => {
+                loop {
+                    let scheduler = &self.bus.io_map.scheduler;
+                    // Instructions can schedule events which are due before `end`, or halt.
+                    if scheduler.now() >= end.min(scheduler.next_event_time())
+                        || self.bus.io_map.power_state() != PowerState::Running
+                    {
+                        break;
+                    }
+                    if self.debugger_enabled
+                        && self.should_break(&self.cpu.get_executing_instruction_pc())
+                    {
+                        self.stopped = true;
+                        break;
+                    }
+                    let cycles = self.cpu.step(&mut self.bus, &self.arm_lut, &self.thumb_lut);
+                    self.bus.io_map.scheduler.advance(u64::from(cycles));
+                }
+                self.bus.io_map.scheduler.now() - start
+            }
+            PowerState::Halted => {
+                // Halts longer than `idle` can count are split up, and the caller runs again
+                // for the rest.
+                let cycles = u32::try_from(end - start).unwrap_or(u32::MAX);
+                self.cpu.idle(&self.bus, cycles);
+                self.bus.io_map.scheduler.advance(u64::from(cycles));
+                u64::from(cycles)
+            }
+            // Time stands still until a keypad interrupt, which can only come from outside.
+            PowerState::Stopped => limit,
+        }
+    }
 }
 
 #[cfg_attr(feature="debugger", wasm_bindgen)]
@@ -58,7 +156,7 @@ impl GbaCore {
     }
 
     pub fn read_halfword(&self, address: u32) -> u32 {
-        self.bus.read_half(address, &self.cpu)
+        self.bus.peek_half(address, &self.cpu)
     }
 
     pub fn thumb_state(&self) -> bool {
@@ -71,14 +169,21 @@ impl GbaCore {
         }
 
         if !self.stopped {
+            self.handle_events();
             self.cpu.tick(&mut self.bus, &self.arm_lut, &self.thumb_lut);
-            self.bus.ppu.tick(&mut self.bus.io_map);
+            if self.bus.io_map.power_state() != PowerState::Stopped {
+                self.bus.io_map.scheduler.advance(1);
+            }
         }
     }
 
+    /// Run for the given number of cycles. The CPU runs whole instructions in batches between
+    /// events, so this can finish a few cycles late.
     pub fn tick_multiple(&mut self, num_ticks: u32) {
-        for _ in 0..num_ticks {
-            self.tick();
+        let mut remaining = u64::from(num_ticks);
+        while remaining > 0 && !self.stopped {
+            self.handle_events();
+            remaining = remaining.saturating_sub(self.run_until_event(remaining));
         }
     }
 
@@ -89,27 +194,45 @@ impl GbaCore {
         }
     }
 
+    /// Load a ROM from JS, which sees the same `load_rom` as before errors were typed.
+    #[cfg_attr(feature = "debugger", wasm_bindgen(js_name = load_rom))]
+    pub fn load_rom_js(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        self.load_rom(bytes).map_err(|err| JsValue::from(err.to_string()))
+    }
+
     pub fn load_test_rom(&mut self) {
         let bytes = include_bytes!("../tests/roms/armwrestler-gba-fixed.gba");
         //let bytes = include_bytes!("../tests/roms/panda.gba");
-        self.load_rom(bytes);
+        self.load_rom(bytes).expect("the test ROM should be valid");
     }
 
-    pub fn load_rom(&mut self, bytes: &[u8]) {
-        self.bus.load_rom(bytes);
+    /// The header of the loaded ROM, for checking what's loaded and whether the dump is intact.
+    pub fn rom_header(&self) -> Option<RomHeader> {
+        self.bus.rom_header().cloned()
     }
 
     pub fn skip_bios(&mut self) {
         self.cpu.skip_bios();
+        self.bus.skip_bios();
+    }
+
+    /// Execute common BIOS functions natively instead of running the BIOS code for them.
+    pub fn set_hle_bios(&mut self, enabled: bool) {
+        self.cpu.hle_bios = enabled;
     }
 
     pub fn reset(self) -> Self {
-        Self {
+        let mut gba = Self {
             stopped: self.stopped,
             arm_breakpoints: self.arm_breakpoints,
             thumb_breakpoints: self.thumb_breakpoints,
             ..Self::default()
-        }
+        };
+        // Keep any BIOS that was loaded, it was already validated.
+        gba.bus.set_bios(self.bus.bios()).unwrap();
+        gba.bus.set_backup_override(self.bus.backup_override());
+        gba.cpu.hle_bios = self.cpu.hle_bios;
+        gba
     }
 
     pub fn enable_debugger(&mut self, enabled: bool) {
@@ -145,7 +268,7 @@ impl GbaCore {
     }
 
     pub fn read_address(&self, address: u32) -> u32 {
-        self.bus.read(address, &self.cpu)
+        self.bus.peek(address, &self.cpu)
     }
 
     pub fn set_key(&mut self, key: bus::Key, pressed: bool) {
@@ -156,6 +279,8 @@ impl GbaCore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bus::{BackupError, BiosError};
+    use crate::Error;
 
     #[test]
     fn write_to_if_clears_bit() {
@@ -168,4 +293,178 @@ mod tests {
 
         assert_eq!(gba.bus.read_half(0x4000202, &gba.cpu), 0);
     }
+
+    #[test]
+    fn load_bios_rejects_wrong_size() {
+        let mut gba = GbaCore::new();
+        assert_eq!(
+            gba.load_bios(&[0; 0x100]),
+            Err(Error::Bios(BiosError::WrongSize(0x100)))
+        );
+
+        let info = gba.load_bios(&[0; 0x4000]).unwrap();
+        assert_eq!(info.kind, bus::BiosKind::Unknown);
+        assert_eq!(info.checksum, 0);
+    }
+
+    #[test]
+    fn boots_through_bios_without_skipping() {
+        let mut gba = GbaCore::new();
+        // The BIOS refuses to boot a cartridge without a valid header.
+        gba.load_rom(include_bytes!("../tests/roms/arm.gba")).unwrap();
+
+        // The intro animation plays for a little over a second.
+        assert!((0..30_000_000).any(|_| {
+            gba.tick();
+            gba.pc() == 0x8000000
+        }));
+    }
+
+    #[test]
+    fn load_rom_rejects_empty_and_oversized_roms() {
+        let mut gba = GbaCore::new();
+        assert_eq!(gba.load_rom(&[]), Err(Error::EmptyRom));
+        assert_eq!(
+            gba.load_rom(&vec![0; 0x2000001]),
+            Err(Error::RomTooLarge(0x2000001))
+        );
+        assert_eq!(gba.load_rom(&vec![0; 0x2000000]), Ok(()));
+    }
+
+    #[test]
+    fn save_import_and_export() {
+        let mut gba = GbaCore::new();
+        gba.set_backup_override(Some(BackupType::Flash(bus::FlashChip::Sanyo)));
+        assert_eq!(gba.export_save(), vec![0xff; 0x20000]);
+
+        assert_eq!(
+            gba.import_save(&[0; 0x10000]),
+            Err(Error::Backup(BackupError::WrongSize {
+                expected: 0x20000,
+                actual: 0x10000
+            }))
+        );
+        let save: Vec<u8> = (0..0x20000).map(|i| i as u8).collect();
+        gba.import_save(&save).unwrap();
+        assert_eq!(gba.export_save(), save);
+        assert_eq!(gba.bus.read_byte(0xe000123, &gba.cpu), 0x23);
+    }
+
+    #[test]
+    fn backup_type_is_detected_unless_overridden() {
+        let mut rom = vec![0; 0x200];
+        rom[0x100..0x10a].copy_from_slice(b"FLASH_V126");
+
+        let mut gba = GbaCore::new();
+        gba.load_rom(&rom).unwrap();
+        assert_eq!(
+            gba.backup_type(),
+            BackupType::Flash(bus::FlashChip::Panasonic)
+        );
+
+        gba.set_backup_override(Some(BackupType::Sram));
+        gba.load_rom(&rom).unwrap();
+        assert_eq!(gba.backup_type(), BackupType::Sram);
+
+        gba.set_backup_override(None);
+        assert_eq!(
+            gba.backup_type(),
+            BackupType::Flash(bus::FlashChip::Panasonic)
+        );
+    }
+
+    #[test]
+    fn reset_keeps_bios_settings() {
+        let mut gba = GbaCore::new();
+        gba.load_bios(&[0; 0x4000]).unwrap();
+        gba.set_backup_override(Some(BackupType::Sram));
+        gba.set_hle_bios(true);
+
+        let gba = gba.reset();
+        assert_eq!(gba.bus.bios(), &[0; 0x4000]);
+        assert_eq!(gba.bus.backup_override(), Some(BackupType::Sram));
+        assert!(gba.cpu.hle_bios);
+    }
+
+    #[test]
+    fn bios_is_only_readable_from_bios() {
+        let mut gba = GbaCore::new();
+        let first_word = u32::from_le_bytes(gba.bus.bios()[..4].try_into().unwrap());
+        assert_eq!(gba.bus.read(0, &gba.cpu), first_word);
+
+        gba.skip_bios();
+        assert_eq!(gba.bus.read(0, &gba.cpu), 0xe129f000);
+        assert_eq!(gba.bus.read_half(0x3ffe, &gba.cpu), 0xe129);
+        assert_eq!(gba.bus.read_byte(0x1, &gba.cpu), 0xf0);
+
+        // Fetching from the BIOS replaces the latched opcode.
+        let cpu = &gba.cpu;
+        let opcode = gba.bus.fetch(0x8, cpu);
+        assert_eq!(gba.bus.read(0x100, cpu), opcode);
+    }
+
+    fn enters_irq_vector(gba: &mut GbaCore) -> bool {
+        (0..100).any(|_| {
+            gba.tick();
+            gba.pc() == 0x18
+        })
+    }
+
+    #[test]
+    fn irq_is_taken_when_enabled() {
+        let mut gba = GbaCore::new();
+        gba.skip_bios();
+        gba.bus.write_half(0x4000200, 1 << 3);
+        gba.bus.write_byte(0x4000208, 1);
+        gba.bus.io_map.set_interrupt(bus::Interrupt::Timer0, true);
+
+        assert!(enters_irq_vector(&mut gba));
+    }
+
+    #[test]
+    fn irq_is_masked_by_ie_and_ime() {
+        let mut gba = GbaCore::new();
+        gba.skip_bios();
+        gba.bus.write_half(0x4000200, 1 << 3);
+        gba.bus.io_map.set_interrupt(bus::Interrupt::Serial, true);
+        gba.bus.io_map.set_interrupt(bus::Interrupt::Timer0, true);
+
+        assert!(!enters_irq_vector(&mut gba));
+    }
+
+    #[test]
+    fn halt_waits_for_enabled_interrupt() {
+        let mut gba = GbaCore::new();
+        gba.skip_bios();
+        gba.tick_multiple(4);
+
+        gba.bus.write_half(0x4000200, 1 << 3);
+        gba.bus.write_byte(0x4000301, 0);
+        let pc = gba.pc();
+        gba.tick_multiple(100);
+        assert_eq!(gba.pc(), pc);
+
+        // IME is clear, so execution resumes without taking the interrupt.
+        gba.bus.io_map.set_interrupt(bus::Interrupt::Timer0, true);
+        gba.tick();
+        assert_eq!(gba.pc(), pc + 4);
+    }
+
+    #[test]
+    fn timer_overflow_wakes_from_halt() {
+        let mut gba = GbaCore::new();
+        gba.skip_bios();
+        gba.tick_multiple(4);
+
+        gba.bus.write_half(0x4000200, 1 << 3);
+        gba.bus.write_half(0x4000100, 0xff00);
+        gba.bus.write_half(0x4000102, 0xc0);
+        gba.bus.write_byte(0x4000301, 0);
+
+        // The whole wait is skipped in one batch, up to the overflow event.
+        gba.tick_multiple(0xff);
+        assert_eq!(gba.bus.io_map.power_state(), PowerState::Halted);
+        gba.tick_multiple(2);
+        assert_eq!(gba.bus.io_map.power_state(), PowerState::Running);
+    }
 }
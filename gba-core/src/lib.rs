@@ -1,11 +1,17 @@
 mod bus;
 mod cpu;
+mod error;
 mod gba;
 mod ppu;
+mod scheduler;
 mod utils;
 
 pub use bus::Bus;
+pub use bus::{BackupError, BackupType, FlashChip};
+pub use bus::{BiosError, BiosInfo, BiosKind};
 pub use bus::Key;
+pub use bus::RomHeader;
 pub use cpu::Cpu;
+pub use error::{Error, Result};
 pub use gba::GbaCore;
 pub use ppu::Ppu;
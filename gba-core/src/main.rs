@@ -13,7 +13,7 @@ fn main() {
     let mut gba = GbaCore::default();
     let bytes = include_bytes!("../tests/roms/armwrestler-gba-fixed.gba");
 
-    gba.load_rom(bytes);
+    gba.load_rom(bytes).unwrap();
     gba.skip_bios();
 
     let mut i = 0;
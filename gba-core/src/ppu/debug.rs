@@ -116,7 +116,11 @@ impl GbaCore {
     }
 
     pub fn get_background_pixel(&self, x: u16, y: u16, bg: usize) -> [u8; 3] {
-        self.bus.ppu.get_bg_0_pixel(x, y, bg)
+        // Transparent pixels show the backdrop, as they would on screen.
+        self.bus
+            .ppu
+            .get_bg_0_pixel(x, y, bg)
+            .unwrap_or_else(|| self.bus.ppu.palette_lookup_256(0))
     }
 
     fn decode_16(&self, palette16: usize, offset: u8) -> [u8; 3] {
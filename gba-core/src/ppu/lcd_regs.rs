@@ -7,25 +7,93 @@ pub struct LcdRegs {
     pub dispcnt: Reg,
     pub green_swap: Reg,
     pub dispstat: Reg,
+    // Read-only, the PPU updates it with `force_write`.
     pub vcount: Reg,
     pub bgcnt: [Reg; 4],
     pub bgofs: [Reg; 8],
+    // PA, PB, PC and PD for BG2 and BG3.
+    pub bg_affine: [[Reg; 4]; 2],
+    // BG2X, BG2Y, BG3X and BG3Y.
+    pub bg_reference: [ReferencePoint; 4],
+    pub winh: [Reg; 2],
+    pub winv: [Reg; 2],
+    pub winin: Reg,
+    pub winout: Reg,
+    pub mosaic: Reg,
+    pub bldcnt: Reg,
+    pub bldalpha: Reg,
+    pub bldy: Reg,
 }
 
 impl Default for LcdRegs {
     fn default() -> Self {
+        // The identity matrix, so affine backgrounds look like text backgrounds until they're
+        // set up.
+        let identity = [
+            Reg::Simple(0x100),
+            Reg::Simple(0),
+            Reg::Simple(0),
+            Reg::Simple(0x100),
+        ];
+
         Self {
             placeholder: Reg::Placeholder,
             dispcnt: Reg::Simple(0),
             green_swap: Reg::Simple(0),
             dispstat: Reg::Masked(Masked::new(0xfff8)),
-            vcount: Reg::Simple(0),
+            vcount: Reg::Masked(Masked::new(0)),
             bgcnt: [Reg::Simple(0); 4],
             bgofs: [Reg::Masked(Masked::new(0x01ff)); 8],
+            bg_affine: [identity; 2],
+            bg_reference: [ReferencePoint::default(); 4],
+            winh: [Reg::Simple(0); 2],
+            winv: [Reg::Simple(0); 2],
+            winin: Reg::Masked(Masked::new(0x3f3f)),
+            winout: Reg::Masked(Masked::new(0x3f3f)),
+            mosaic: Reg::Simple(0),
+            bldcnt: Reg::Masked(Masked::new(0x3fff)),
+            bldalpha: Reg::Masked(Masked::new(0x1f1f)),
+            bldy: Reg::Masked(Masked::new(0x001f)),
         }
     }
 }
 
+/// One coordinate of an affine background's reference point, a signed 20.8 fixed point number
+/// in 28 bits. The renderer uses an internal copy, which is advanced by PB or PD after every
+/// line and reloaded from the register at VBlank or when the register is written.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReferencePoint {
+    value: u32,
+    internal: i32,
+}
+
+impl ReferencePoint {
+    /// The written value, sign extended.
+    pub fn value(&self) -> i32 {
+        ((self.value << 4) as i32) >> 4
+    }
+
+    /// The current position, which has been moved down one line for every line drawn.
+    pub fn internal(&self) -> i32 {
+        self.internal
+    }
+
+    pub fn reload(&mut self) {
+        self.internal = self.value();
+    }
+
+    pub fn advance(&mut self, step: i16) {
+        self.internal = self.internal.wrapping_add(step.into());
+    }
+
+    fn write_byte(&mut self, index: usize, value: u8) {
+        let mut bytes = self.value.to_le_bytes();
+        bytes[index] = value;
+        self.value = u32::from_le_bytes(bytes) & 0x0fff_ffff;
+        self.reload();
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Reg {
     Simple(u16),
@@ -84,6 +152,16 @@ impl LcdRegs {
             0x6 => &self.vcount,
             0x8..=0xf => &self.bgcnt[(index - 0x4000008) / 2],
             0x10..=0x1f => &self.bgofs[(index - 0x4000010) / 2],
+            0x20..=0x27 => &self.bg_affine[0][(index - 0x4000020) / 2],
+            0x30..=0x37 => &self.bg_affine[1][(index - 0x4000030) / 2],
+            0x40..=0x43 => &self.winh[(index - 0x4000040) / 2],
+            0x44..=0x47 => &self.winv[(index - 0x4000044) / 2],
+            0x48 => &self.winin,
+            0x4a => &self.winout,
+            0x4c => &self.mosaic,
+            0x50 => &self.bldcnt,
+            0x52 => &self.bldalpha,
+            0x54 => &self.bldy,
             _ => &self.placeholder,
         }
     }
@@ -98,12 +176,39 @@ impl LcdRegs {
             0x6 => &mut self.vcount,
             0x8..=0xf => &mut self.bgcnt[(index - 0x4000008) / 2],
             0x10..=0x1f => &mut self.bgofs[(index - 0x4000010) / 2],
+            0x20..=0x27 => &mut self.bg_affine[0][(index - 0x4000020) / 2],
+            0x30..=0x37 => &mut self.bg_affine[1][(index - 0x4000030) / 2],
+            0x40..=0x43 => &mut self.winh[(index - 0x4000040) / 2],
+            0x44..=0x47 => &mut self.winv[(index - 0x4000044) / 2],
+            0x48 => &mut self.winin,
+            0x4a => &mut self.winout,
+            0x4c => &mut self.mosaic,
+            0x50 => &mut self.bldcnt,
+            0x52 => &mut self.bldalpha,
+            0x54 => &mut self.bldy,
             _ => &mut self.placeholder,
         }
     }
 
+    /// Whether a register can only be written, in which case reads from the CPU return open
+    /// bus.
+    pub fn is_write_only(index: usize) -> bool {
+        matches!(index - 0x4000000, 0x10..=0x47 | 0x4c..=0x4f | 0x54..=0x55)
+    }
+
+    /// The index of the reference point register containing a byte, if there is one.
+    fn reference_point(index: usize) -> Option<usize> {
+        match index - 0x4000000 {
+            0x28..=0x2f => Some((index - 0x4000028) / 4),
+            0x38..=0x3f => Some(2 + (index - 0x4000038) / 4),
+            _ => None,
+        }
+    }
+
     pub fn read_byte(&self, index: usize) -> u8 {
-        if index & 1 == 0 {
+        if let Some(point) = Self::reference_point(index) {
+            self.bg_reference[point].value.to_le_bytes()[index % 4]
+        } else if index & 1 == 0 {
             self.get_halfword(index).read() as u8
         } else {
             (self.get_halfword(index - 1).read() >> 8) as u8
@@ -111,16 +216,81 @@ impl LcdRegs {
     }
 
     pub fn write_byte(&mut self, index: usize, value: u8) {
-        if index & 1 == 0 {
+        if let Some(point) = Self::reference_point(index) {
+            self.bg_reference[point].write_byte(index % 4, value);
+        } else if index & 1 == 0 {
             let mem = self.get_halfword_mut(index);
-            mem.write(mem.read().bits(8, 15) | u16::from(value));
+            mem.write((mem.read() & 0xff00) | u16::from(value));
         } else {
             let mem = self.get_halfword_mut(index - 1);
             mem.write(mem.read().bits(0, 7) | (u16::from(value) << 8));
         }
     }
 
+    /// Copy the reference points into their internal registers, at the start of VBlank.
+    pub fn reload_reference_points(&mut self) {
+        for point in &mut self.bg_reference {
+            point.reload();
+        }
+    }
+
+    /// Move the reference points down a line, by PB for X and PD for Y.
+    pub fn advance_reference_points(&mut self) {
+        for (bg, affine) in self.bg_affine.iter().enumerate() {
+            self.bg_reference[2 * bg].advance(affine[1].read() as i16);
+            self.bg_reference[2 * bg + 1].advance(affine[3].read() as i16);
+        }
+    }
+
     pub fn get_bg_mode(&self) -> u8 {
         u8::try_from(self.dispcnt.read() & 0b111).unwrap().min(5)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_writes_keep_other_byte() {
+        let mut regs = LcdRegs::default();
+        regs.write_byte(0x4000050, 0x41);
+        regs.write_byte(0x4000051, 0x3f);
+        regs.write_byte(0x4000050, 0x42);
+        assert_eq!(regs.bldcnt.read(), 0x3f42);
+        // Bits 14 and 15 don't exist.
+        regs.write_byte(0x4000051, 0xff);
+        assert_eq!(regs.bldcnt.read(), 0x3f42);
+    }
+
+    #[test]
+    fn vcount_is_read_only() {
+        let mut regs = LcdRegs::default();
+        regs.write_byte(0x4000006, 0x12);
+        assert_eq!(regs.read_byte(0x4000006), 0);
+    }
+
+    #[test]
+    fn reference_points_are_latched() {
+        let mut regs = LcdRegs::default();
+        // BG2X = -1.5, written a byte at a time.
+        for (i, byte) in 0x0fff_fe80u32.to_le_bytes().into_iter().enumerate() {
+            regs.write_byte(0x4000028 + i, byte);
+        }
+        assert_eq!(regs.bg_reference[0].value(), -0x180);
+        assert_eq!(regs.bg_reference[0].internal(), -0x180);
+
+        // BG2PB = 2.0
+        regs.write_byte(0x4000022, 0x00);
+        regs.write_byte(0x4000023, 0x02);
+        regs.advance_reference_points();
+        regs.advance_reference_points();
+        assert_eq!(regs.bg_reference[0].internal(), 0x280);
+        // BG2Y was advanced by the default PD of 1.0.
+        assert_eq!(regs.bg_reference[1].internal(), 0x200);
+
+        regs.reload_reference_points();
+        assert_eq!(regs.bg_reference[0].internal(), -0x180);
+        assert_eq!(regs.bg_reference[1].internal(), 0);
+    }
+}
@@ -3,6 +3,7 @@ use std::cmp;
 mod dispstat;
 mod lcd_regs;
 mod masked_byte;
+mod objects;
 mod utils;
 mod debug;
 
@@ -11,8 +12,9 @@ use wasm_bindgen::prelude::wasm_bindgen;
 use js_sys;
 
 use crate::{
-    bus::{Interrupt, IoMap},
+    bus::{DmaTiming, Interrupt, IoMap},
     ppu::utils::decode_color,
+    scheduler::{Event, Scheduler},
     utils::{get, set, AddressableBits},
 };
 
@@ -24,6 +26,9 @@ const SCREEN_HEIGHT: u16 = 160;
 const SCREEN_AREA: u16 = SCREEN_WIDTH * SCREEN_HEIGHT;
 const H_BLANK_WIDTH: u16 = 68;
 const V_BLANK_HEIGHT: u16 = 68;
+/// Each pixel takes 4 cycles, including the ones in HBlank.
+const HDRAW_CYCLES: u64 = 4 * SCREEN_WIDTH as u64;
+const LINE_CYCLES: u64 = 4 * (SCREEN_WIDTH + H_BLANK_WIDTH) as u64;
 
 #[cfg_attr(feature="debugger", wasm_bindgen)]
 pub struct Ppu {
@@ -32,13 +37,6 @@ pub struct Ppu {
     pub(super) vram: Vec<u8>,
     oam: Vec<u8>,
 
-    /* Renderer */
-    // Counts 3-2-1-0
-    pixel_timer: u8,
-    // Current location on screen (or off-screen, during H/V-blank)
-    x: u16,
-    //y: u16, See lcd_regs.vcount
-    //
     screen: Vec<u8>,
 }
 
@@ -66,9 +64,6 @@ impl Default for Ppu {
             vram: vec![0; 0x18000],
             oam: vec![0; 0x400],
 
-            pixel_timer: 0,
-            x: 0,
-
             screen: vec![0; usize::from(SCREEN_AREA) * 3],
         }
     }
@@ -105,6 +100,11 @@ impl Ppu {
         }
     }
 
+    /// Whether an LCD register is write-only, so reads from the CPU return open bus.
+    pub fn is_write_only(index: usize) -> bool {
+        LcdRegs::is_write_only(index)
+    }
+
     // Side effects out the wazoo
     pub fn read_lcd_io_regs<T, const N: usize>(&self, index: usize) -> T
     where
@@ -132,20 +132,21 @@ impl Ppu {
         self.lcd_regs.dispcnt.read().bits(0, 2) as u8
     }
 
-    fn get_bg_0_pixel(&self, x: u16, y: u16, bg: usize) -> [u8; 3] {
+    /// Sample a text background, or return `None` if the pixel is transparent.
+    fn get_bg_0_pixel(&self, x: u16, y: u16, bg: usize) -> Option<[u8; 3]> {
         let bg_cnt = self.lcd_regs.bgcnt[bg].read();
 
         let character_base_block = usize::from(bg_cnt.bits(2, 3)) * 0x4000;
         let screen_base_block = usize::from(bg_cnt.bits(8, 12)) * 0x800;
-        let scroll_x: u16 = self.read_lcd_io_regs(0x4000010);
-        let scroll_y: u16 = self.read_lcd_io_regs(0x4000012);
+        let scroll_x = self.lcd_regs.bgofs[2 * bg].read();
+        let scroll_y = self.lcd_regs.bgofs[2 * bg + 1].read();
 
         let background_x = x + scroll_x;
         let background_y = y + scroll_y;
 
         let mut tile_x = background_x / 8;
         let mut tile_y = background_y / 8;
-        let screenblock = self.reg_screenblock(tile_x.into(), tile_y.into());
+        let screenblock = self.reg_screenblock(bg, tile_x.into(), tile_y.into());
         tile_x %= 32;
         tile_y %= 32;
 
@@ -168,6 +169,21 @@ impl Ppu {
         }
 
         let ts_index: usize = tm_data.bits(0, 9).into();
+        if bg_cnt.bit(7) == 1 {
+            // 256 colour tiles take 64 bytes, one per pixel, and ignore the palette bank.
+            let ts_offset = character_base_block + 64 * ts_index + 8 * subpixel_y + subpixel_x;
+            // Backgrounds can't read tiles from OBJ VRAM.
+            let palette_offset = if ts_offset < 0x10000 {
+                self.vram[ts_offset]
+            } else {
+                0
+            };
+            if palette_offset == 0 {
+                return None;
+            }
+            return Some(self.palette_lookup_256(palette_offset.into()));
+        }
+
         let ts_byte = self.vram
             [character_base_block + 32 * ts_index + 4 * subpixel_y + subpixel_x / 2];
 
@@ -177,10 +193,59 @@ impl Ppu {
             ts_byte.bits(4, 7)
         };
 
+        if palette_offset == 0 {
+            return None;
+        }
         let palette_bank = tm_data.bits(12, 15);
-        let color = self.palette_lookup_16(palette_bank.into(), palette_offset.into());
+        Some(self.palette_lookup_16(palette_bank.into(), palette_offset.into()))
+    }
 
-        color
+    /// Whether a background is drawn with an affine transformation in the current mode.
+    fn is_affine(&self, bg: usize) -> bool {
+        match self.bg_mode() {
+            1 => bg == 2,
+            2 => bg >= 2,
+            _ => false,
+        }
+    }
+
+    /// Sample BG2 or BG3 in an affine mode, or return `None` if the pixel is transparent. The
+    /// line is chosen by the internal reference point rather than the screen position.
+    fn get_affine_bg_pixel(&self, x: u16, bg: usize) -> Option<[u8; 3]> {
+        let bg_cnt = self.lcd_regs.bgcnt[bg].read();
+        let character_base_block = usize::from(bg_cnt.bits(2, 3)) * 0x4000;
+        let screen_base_block = usize::from(bg_cnt.bits(8, 12)) * 0x800;
+        let wraparound = bg_cnt.bit(13) == 1;
+        // Affine maps are square, from 16x16 to 128x128 tiles.
+        let size = 128 << bg_cnt.bits(14, 15);
+
+        let affine = &self.lcd_regs.bg_affine[bg - 2];
+        let pa = i32::from(affine[0].read() as i16);
+        let pc = i32::from(affine[2].read() as i16);
+        let reference_x = self.lcd_regs.bg_reference[2 * (bg - 2)].internal();
+        let reference_y = self.lcd_regs.bg_reference[2 * (bg - 2) + 1].internal();
+
+        // Reference points and parameters have 8 fractional bits.
+        let mut background_x = (reference_x + pa * i32::from(x)) >> 8;
+        let mut background_y = (reference_y + pc * i32::from(x)) >> 8;
+        if wraparound {
+            background_x = background_x.rem_euclid(size);
+            background_y = background_y.rem_euclid(size);
+        } else if !(0..size).contains(&background_x) || !(0..size).contains(&background_y) {
+            return None;
+        }
+        let (background_x, background_y) = (background_x as usize, background_y as usize);
+
+        // Affine maps have one byte per tile, and always use 256 colour tiles.
+        let tile_index = background_y / 8 * (size as usize / 8) + background_x / 8;
+        let tile = usize::from(self.vram[(screen_base_block + tile_index) % 0x10000]);
+        let tile_offset = tile * 64 + (background_y % 8) * 8 + background_x % 8;
+        let palette_offset = self.vram[(character_base_block + tile_offset) % 0x10000];
+
+        if palette_offset == 0 {
+            return None;
+        }
+        Some(self.palette_lookup_256(palette_offset.into()))
     }
 
     fn get_bg_3_pixel(&self, x: u16, y: u16) -> [u8; 3] {
@@ -211,40 +276,49 @@ impl Ppu {
         decode_color(color)
     }
 
-    fn get_bg(&self) -> usize {
-        [0usize, 1, 2, 3].into_iter().filter(|bg| {
-            // display_enable bit from DISPCNT takes up bits 8 to 11 for bgs 0 to 3
-            let display_enable = (self.lcd_regs.dispcnt.read() >> (bg + 8)) & 1 == 1;
-            display_enable
-        }).min_by_key(|&bg| {
-            let bg_cnt = self.lcd_regs.bgcnt[bg].read();
-            bg_cnt & 0b11
-        }).unwrap_or(0)
-        // TODO: find behaviour when all backgrounds are disabled
+    /// The enabled backgrounds from front to back. Each mode only has some of the four
+    /// backgrounds, and backgrounds with the same priority are ordered by number.
+    fn bg_layers(&self) -> Vec<usize> {
+        let layers = match self.bg_mode() {
+            0 => 0..4,
+            1 => 0..3,
+            2 => 2..4,
+            _ => 2..3,
+        };
+        let mut layers: Vec<usize> = layers
+            .filter(|bg| {
+                // display_enable bit from DISPCNT takes up bits 8 to 11 for bgs 0 to 3
+                self.lcd_regs.dispcnt.read().bit(bg + 8) == 1
+            })
+            .collect();
+        layers.sort_by_key(|&bg| (self.lcd_regs.bgcnt[bg].read().bits(0, 1), bg));
+        layers
     }
 
-    fn get_pixel(&self) -> [u8; 3] {
-        let x = self.x;
-        let y = self.lcd_regs.vcount.read();
+    /// The colour of a background at a pixel, or `None` if the pixel is transparent.
+    fn get_layer_pixel(&self, x: u16, y: u16, bg: usize) -> Option<[u8; 3]> {
         match self.bg_mode() {
-            0 => {
-                let bg = self.get_bg();
-                self.get_bg_0_pixel(x, y, bg)
-            }
-            1 => {
-                let bg = self.get_bg();
-                self.get_bg_0_pixel(x, y, bg)
-            }
-            2 => [0, 255, 0],
-            3 => self.get_bg_3_pixel(x, y),
-            4 => self.get_bg_4_pixel(x, y),
-            5 => self.get_bg_3_pixel(x, y),
-            _ => [255, 255, 255],
+            1 | 2 if self.is_affine(bg) => self.get_affine_bg_pixel(x, bg),
+            0 | 1 => self.get_bg_0_pixel(x, y, bg),
+            3 => Some(self.get_bg_3_pixel(x, y)),
+            4 => Some(self.get_bg_4_pixel(x, y)),
+            5 => Some(self.get_bg_3_pixel(x, y)),
+            _ => Some([255, 255, 255]),
         }
     }
 
-    fn reg_screenblock(&self, tile_x: usize, tile_y: usize) -> usize {
-        match self.lcd_regs.bgcnt[0].read().bits(14, 15) {
+    /// The colour of the front opaque background at a pixel and its priority, or `None` if
+    /// every layer is transparent there.
+    fn get_bg_pixel(&self, x: u16, y: u16, layers: &[usize]) -> Option<([u8; 3], u16)> {
+        layers.iter().find_map(|&bg| {
+            let color = self.get_layer_pixel(x, y, bg)?;
+            Some((color, self.lcd_regs.bgcnt[bg].read().bits(0, 1)))
+        })
+    }
+
+    /// Which of a text background's screenblocks a tile is in, for maps larger than 32x32 tiles.
+    fn reg_screenblock(&self, bg: usize, tile_x: usize, tile_y: usize) -> usize {
+        match self.lcd_regs.bgcnt[bg].read().bits(14, 15) {
             0 => 0,
             1 => (tile_x % 64) / 32,
             2 => (tile_y % 64) / 32,
@@ -282,72 +356,106 @@ impl Ppu {
             .collect()
     }
 
-    pub fn tick(&mut self, io_map: &mut IoMap) {
-        if self.pixel_timer == 0 {
-            self.pixel_timer = 3;
+    /// Queue the events for the first scanline, which starts at cycle 0.
+    pub fn schedule_first_line(scheduler: &mut Scheduler) {
+        scheduler.schedule_at(Event::HBlank, HDRAW_CYCLES);
+        scheduler.schedule_at(Event::HDraw, LINE_CYCLES);
+    }
 
-            // Draw pixel
-            if self.x < SCREEN_WIDTH && self.lcd_regs.vcount.read() < SCREEN_HEIGHT {
-                let pixel = self.get_pixel();
-                let pixel_index = usize::from(self.x + self.lcd_regs.vcount.read() * SCREEN_WIDTH);
-                self.screen[3 * pixel_index..3 * pixel_index + 3].clone_from_slice(&pixel);
-            }
+    fn draw_line(&mut self, y: u16) {
+        let obj_line = self.obj_line(y);
+        // Palette entry 0 is shown wherever nothing else is.
+        let backdrop = self.palette_lookup_256(0);
+        let layers = self.bg_layers();
+
+        for x in 0..SCREEN_WIDTH {
+            // OBJs are drawn in front of backgrounds with the same priority.
+            let pixel = match (obj_line[usize::from(x)], self.get_bg_pixel(x, y, &layers)) {
+                (Some(obj), Some((_, priority))) if obj.priority <= priority => obj.color,
+                (_, Some((color, _))) => color,
+                (Some(obj), None) => obj.color,
+                (None, None) => backdrop,
+            };
+            let pixel_index = usize::from(x + y * SCREEN_WIDTH);
+            self.screen[3 * pixel_index..3 * pixel_index + 3].clone_from_slice(&pixel);
+        }
+    }
 
-            self.x += 1;
-            if self.x == SCREEN_WIDTH + H_BLANK_WIDTH {
-                self.x = 0;
-                self.lcd_regs.vcount.write(self.lcd_regs.vcount.read() + 1);
-                if self.lcd_regs.vcount.read() == SCREEN_HEIGHT + V_BLANK_HEIGHT {
-                    // New frame
-                    self.lcd_regs.vcount.write(0);
-                }
+    /// Handle `Event::HBlank`, at the end of the visible part of a scanline. The whole line is
+    /// drawn here, so register writes made partway through a line only show up on the next one.
+    pub fn hblank(&mut self, io_map: &mut IoMap, time: u64) {
+        let vcount = self.lcd_regs.vcount.read();
+        if vcount < SCREEN_HEIGHT {
+            self.draw_line(vcount);
+            self.lcd_regs.advance_reference_points();
+        }
 
-                // Check VCount == LYC and send VCount interrupt if true
-                if self.lcd_regs.vcount.read() == self.lcd_regs.dispstat.read().bits(8, 15)
-                    && self
-                        .lcd_regs
-                        .dispstat
-                        .read()
-                        .bit(Dispstat::VCountIrq.into())
-                        == 1
-                {
-                    io_map.set_interrupt(Interrupt::VCount, true);
-                }
-            }
+        self.set_dispstat_bit(Dispstat::HBlank.into(), true);
 
-            if self.x == 0 {
-                self.set_dispstat_bit(Dispstat::HBlank.into(), false);
-                if self.lcd_regs.vcount.read() == 0 {
-                    self.set_dispstat_bit(Dispstat::VBlank.into(), false);
-                } else if self.lcd_regs.vcount.read() == SCREEN_HEIGHT {
-                    self.set_dispstat_bit(Dispstat::VBlank.into(), true);
-
-                    if self
-                        .lcd_regs
-                        .dispstat
-                        .read()
-                        .bit(Dispstat::VBlankIrq.into())
-                        == 1
-                    {
-                        io_map.set_interrupt(Interrupt::VBlank, true);
-                    }
-                }
-            } else if self.x == SCREEN_WIDTH {
-                self.set_dispstat_bit(Dispstat::HBlank.into(), true);
-
-                if self
-                    .lcd_regs
-                    .dispstat
-                    .read()
-                    .bit(Dispstat::HBlankIrq.into())
-                    == 1
-                {
-                    io_map.set_interrupt(Interrupt::HBlank, true);
-                }
+        // HBlank DMAs don't run during VBlank.
+        if vcount < SCREEN_HEIGHT {
+            io_map.trigger_dma(DmaTiming::HBlank);
+        }
+        // Video capture DMA runs from line 2 to line 161.
+        if (2..SCREEN_HEIGHT + 2).contains(&vcount) {
+            io_map.trigger_dma(DmaTiming::Special);
+        } else if vcount == SCREEN_HEIGHT + 2 {
+            io_map.dma.stop_video_capture();
+        }
+
+        if self
+            .lcd_regs
+            .dispstat
+            .read()
+            .bit(Dispstat::HBlankIrq.into())
+            == 1
+        {
+            io_map.set_interrupt(Interrupt::HBlank, true);
+        }
+
+        io_map.scheduler.schedule_at(Event::HBlank, time + LINE_CYCLES);
+    }
+
+    /// Handle `Event::HDraw`, which starts the next scanline.
+    pub fn hdraw(&mut self, io_map: &mut IoMap, time: u64) {
+        self.set_dispstat_bit(Dispstat::HBlank.into(), false);
+
+        let vcount = (self.lcd_regs.vcount.read() + 1) % (SCREEN_HEIGHT + V_BLANK_HEIGHT);
+        self.lcd_regs.vcount.force_write(vcount);
+
+        // Check VCount == LYC and send VCount interrupt if true
+        let vcount_match = vcount == self.lcd_regs.dispstat.read().bits(8, 15);
+        self.set_dispstat_bit(Dispstat::VCount.into(), vcount_match);
+        if vcount_match
+            && self
+                .lcd_regs
+                .dispstat
+                .read()
+                .bit(Dispstat::VCountIrq.into())
+                == 1
+        {
+            io_map.set_interrupt(Interrupt::VCount, true);
+        }
+
+        if vcount == 0 {
+            self.set_dispstat_bit(Dispstat::VBlank.into(), false);
+        } else if vcount == SCREEN_HEIGHT {
+            self.set_dispstat_bit(Dispstat::VBlank.into(), true);
+            self.lcd_regs.reload_reference_points();
+            io_map.trigger_dma(DmaTiming::VBlank);
+
+            if self
+                .lcd_regs
+                .dispstat
+                .read()
+                .bit(Dispstat::VBlankIrq.into())
+                == 1
+            {
+                io_map.set_interrupt(Interrupt::VBlank, true);
             }
-        } else {
-            self.pixel_timer -= 1;
         }
+
+        io_map.scheduler.schedule_at(Event::HDraw, time + LINE_CYCLES);
     }
 
     fn set_dispstat_bit(&mut self, bit: usize, value: bool) {
@@ -434,4 +542,237 @@ mod tests {
         assert_eq!(ppu.vram[3], 1);
         assert_eq!(ppu.read_simple::<u32, 4>(0x6000000), 0x01020304);
     }
+
+    fn pixel(ppu: &Ppu, x: usize, y: usize) -> [u8; 3] {
+        let index = 3 * (x + y * usize::from(SCREEN_WIDTH));
+        ppu.screen[index..index + 3].try_into().unwrap()
+    }
+
+    /// An 8x8 OBJ at (10, 5) using tile 1 and OBJ palette bank 1, with only its top left pixel
+    /// drawn in red. The backdrop is blue.
+    fn ppu_with_obj(attr0: u16, attr1: u16, attr2: u16) -> Ppu {
+        let mut ppu = Ppu::default();
+        ppu.write_simple::<u16, 2>(0x5000000, 0x7c00);
+        ppu.write_simple::<u16, 2>(0x5000200 + 2 * (16 + 2), 0x001f);
+        ppu.write_simple::<u8, 1>(0x6010000 + 32, 0x02);
+        ppu.write_simple::<u16, 2>(0x7000000, 5 | attr0);
+        ppu.write_simple::<u16, 2>(0x7000002, 10 | attr1);
+        ppu.write_simple::<u16, 2>(0x7000004, 1 | 1 << 12 | attr2);
+        // OBJs enabled with 1D mapping.
+        ppu.lcd_regs.dispcnt.write(0x1040);
+        ppu
+    }
+
+    #[test]
+    fn draws_regular_obj() {
+        let mut ppu = ppu_with_obj(0, 0, 0);
+        ppu.draw_line(5);
+        assert_eq!(pixel(&ppu, 10, 5), [255, 0, 0]);
+        assert_eq!(pixel(&ppu, 11, 5), [0, 0, 255]);
+
+        // Flipped horizontally.
+        let mut ppu = ppu_with_obj(0, 1 << 12, 0);
+        ppu.draw_line(5);
+        assert_eq!(pixel(&ppu, 10, 5), [0, 0, 255]);
+        assert_eq!(pixel(&ppu, 17, 5), [255, 0, 0]);
+    }
+
+    #[test]
+    fn obj_priority_against_backgrounds() {
+        // BG0 with priority 1 shows green everywhere, from tile 0 of character base block 1.
+        let mut ppu = ppu_with_obj(0, 0, 1 << 10);
+        ppu.write_simple::<u16, 2>(0x5000002, 0x03e0);
+        for i in 0..32 {
+            ppu.write_simple::<u8, 1>(0x6004000 + i, 0x11);
+        }
+        ppu.lcd_regs.dispcnt.write(0x1140);
+        ppu.lcd_regs.bgcnt[0].write(1 | 1 << 2);
+        ppu.draw_line(5);
+        assert_eq!(pixel(&ppu, 10, 5), [255, 0, 0]);
+
+        // OBJs behind the background are hidden.
+        ppu.lcd_regs.bgcnt[0].write(1 << 2);
+        ppu.draw_line(5);
+        assert_eq!(pixel(&ppu, 10, 5), [0, 255, 0]);
+    }
+
+    #[test]
+    fn draws_affine_obj() {
+        // Double size, using affine parameter group 1.
+        let mut ppu = ppu_with_obj(0x300, 1 << 9, 0);
+        // Mirror horizontally: PA = -1.0, PD = 1.0.
+        ppu.write_simple::<u16, 2>(0x7000000 + 32 + 6, 0xff00);
+        ppu.write_simple::<u16, 2>(0x7000000 + 32 + 30, 0x0100);
+
+        // The OBJ is centred in a 16x16 box, so its top row is on line 9.
+        ppu.draw_line(9);
+        assert_eq!(pixel(&ppu, 22, 9), [255, 0, 0]);
+        assert_eq!(pixel(&ppu, 21, 9), [0, 0, 255]);
+        assert_eq!(pixel(&ppu, 14, 9), [0, 0, 255]);
+    }
+
+    #[test]
+    fn regular_obj_with_bit_9_is_hidden() {
+        let mut ppu = ppu_with_obj(0x200, 0, 0);
+        ppu.draw_line(5);
+        assert_eq!(pixel(&ppu, 10, 5), [0, 0, 255]);
+    }
+
+    #[test]
+    fn draws_wide_obj() {
+        // 16x8, so the second tile is next to the first and there's no second row.
+        let mut ppu = ppu_with_obj(0x4000, 0, 0);
+        ppu.write_simple::<u8, 1>(0x6010000 + 2 * 32, 0x02);
+        ppu.write_simple::<u8, 1>(0x6010000 + 3 * 32, 0x02);
+        ppu.draw_line(5);
+        assert_eq!(pixel(&ppu, 18, 5), [255, 0, 0]);
+        ppu.draw_line(13);
+        assert_eq!(pixel(&ppu, 10, 13), [0, 0, 255]);
+    }
+
+    #[test]
+    fn draws_256_color_obj() {
+        // 16x8 with 256 colours, so each tile takes two tile numbers.
+        let mut ppu = ppu_with_obj(0x4000 | 1 << 13, 0, 0);
+        ppu.write_simple::<u16, 2>(0x5000200 + 2 * 5, 0x03e0);
+        ppu.write_simple::<u8, 1>(0x6010000 + 3 * 32, 0x05);
+        ppu.draw_line(5);
+        assert_eq!(pixel(&ppu, 18, 5), [0, 255, 0]);
+        assert_eq!(pixel(&ppu, 11, 5), [0, 0, 255]);
+    }
+
+    #[test]
+    fn draws_obj_with_2d_mapping() {
+        // 8x16, where the second row comes from 32 tiles further on with 2D mapping.
+        let mut ppu = ppu_with_obj(0x8000, 0, 0);
+        ppu.write_simple::<u8, 1>(0x6010000 + 33 * 32, 0x02);
+        ppu.lcd_regs.dispcnt.write(0x1000);
+        ppu.draw_line(13);
+        assert_eq!(pixel(&ppu, 10, 13), [255, 0, 0]);
+
+        // With 1D mapping the second row is the next tile, which is empty.
+        ppu.lcd_regs.dispcnt.write(0x1040);
+        ppu.draw_line(13);
+        assert_eq!(pixel(&ppu, 10, 13), [0, 0, 255]);
+    }
+
+    #[test]
+    fn objs_past_the_cycle_budget_are_dropped() {
+        // Put 18 off screen 64x64 OBJ window masks first, which take 1152 cycles between them.
+        let mut ppu = ppu_with_obj(0, 0, 0);
+        for index in 0..18 {
+            ppu.write_simple::<u16, 2>(0x7000000 + 8 * index, 5 | 2 << 10);
+            ppu.write_simple::<u16, 2>(0x7000002 + 8 * index, 300 | 3 << 14);
+            ppu.write_simple::<u16, 2>(0x7000004 + 8 * index, 0);
+        }
+        ppu.write_simple::<u16, 2>(0x7000000 + 8 * 18, 5);
+        ppu.write_simple::<u16, 2>(0x7000002 + 8 * 18, 10);
+        ppu.write_simple::<u16, 2>(0x7000004 + 8 * 18, 1 | 1 << 12);
+
+        // The 8 cycles the last OBJ needs fit into the 1210 cycles of a line.
+        ppu.draw_line(5);
+        assert_eq!(pixel(&ppu, 10, 5), [255, 0, 0]);
+
+        // Leaving HBlank free cuts the budget to 954 cycles.
+        ppu.lcd_regs.dispcnt.write(0x1060);
+        ppu.draw_line(5);
+        assert_eq!(pixel(&ppu, 10, 5), [0, 0, 255]);
+    }
+
+    #[test]
+    fn draws_affine_background() {
+        let mut ppu = Ppu::default();
+        ppu.write_simple::<u16, 2>(0x5000000, 0x7c00);
+        ppu.write_simple::<u16, 2>(0x5000000 + 2 * 3, 0x001f);
+        // Tile 1 has only its top left pixel set, and is the first entry of the map.
+        ppu.write_simple::<u8, 1>(0x6000000 + 64, 3);
+        ppu.write_simple::<u8, 1>(0x6000800, 1);
+        // Mode 2 with BG2 enabled, using screen base block 1 and a 128x128 map.
+        ppu.lcd_regs.dispcnt.write(0x0402);
+        ppu.lcd_regs.bgcnt[2].write(1 << 8);
+        ppu.draw_line(0);
+        assert_eq!(pixel(&ppu, 0, 0), [255, 0, 0]);
+        assert_eq!(pixel(&ppu, 1, 0), [0, 0, 255]);
+
+        // Move the map 128 pixels right, which only shows it again with wraparound.
+        for (i, byte) in (-0x8000i32).to_le_bytes().into_iter().enumerate() {
+            ppu.lcd_regs.write_byte(0x4000028 + i, byte);
+        }
+        ppu.draw_line(0);
+        assert_eq!(pixel(&ppu, 0, 0), [0, 0, 255]);
+        ppu.lcd_regs.bgcnt[2].write(1 << 8 | 1 << 13);
+        ppu.draw_line(0);
+        assert_eq!(pixel(&ppu, 0, 0), [255, 0, 0]);
+    }
+
+    /// Mode 0 with BG0 and BG1 using the same map in screenblock 1, whose first entry is tile 1
+    /// in palette bank 1. The backdrop is blue and the tile's top left pixel is red.
+    fn ppu_with_text_bgs() -> Ppu {
+        let mut ppu = Ppu::default();
+        ppu.write_simple::<u16, 2>(0x5000000, 0x7c00);
+        ppu.write_simple::<u16, 2>(0x5000000 + 2 * (16 + 2), 0x001f);
+        ppu.write_simple::<u8, 1>(0x6000000 + 32, 0x02);
+        ppu.write_simple::<u16, 2>(0x6000800, 1 | 1 << 12);
+        ppu.lcd_regs.dispcnt.write(0x0300);
+        ppu.lcd_regs.bgcnt[0].write(1 << 8);
+        ppu.lcd_regs.bgcnt[1].write(1 << 8);
+        ppu
+    }
+
+    #[test]
+    fn transparent_background_pixels_show_lower_layers() {
+        let mut ppu = ppu_with_text_bgs();
+        // BG1 is drawn under BG0 and moved one pixel left.
+        ppu.lcd_regs.bgofs[2].write(0x1ff);
+        ppu.draw_line(0);
+        assert_eq!(pixel(&ppu, 0, 0), [255, 0, 0]);
+        assert_eq!(pixel(&ppu, 1, 0), [255, 0, 0]);
+        assert_eq!(pixel(&ppu, 2, 0), [0, 0, 255]);
+    }
+
+    #[test]
+    fn background_priority_ties_are_broken_by_number() {
+        let mut ppu = ppu_with_text_bgs();
+        // Give BG1 palette bank 2 by putting its map in screenblock 2.
+        ppu.write_simple::<u16, 2>(0x5000000 + 2 * (32 + 2), 0x03e0);
+        ppu.write_simple::<u16, 2>(0x6001000, 1 | 2 << 12);
+        ppu.lcd_regs.bgcnt[1].write(2 << 8);
+        ppu.draw_line(0);
+        assert_eq!(pixel(&ppu, 0, 0), [255, 0, 0]);
+
+        ppu.lcd_regs.bgcnt[0].write(1 << 8 | 1);
+        ppu.draw_line(0);
+        assert_eq!(pixel(&ppu, 0, 0), [0, 255, 0]);
+    }
+
+    #[test]
+    fn large_text_background_uses_its_own_size() {
+        let mut ppu = ppu_with_text_bgs();
+        ppu.lcd_regs.dispcnt.write(0x0200);
+        // A 512x256 map, scrolled so the screen starts in the second screenblock.
+        ppu.lcd_regs.bgcnt[1].write(1 << 14);
+        ppu.lcd_regs.bgofs[2].write(256);
+        ppu.draw_line(0);
+        assert_eq!(pixel(&ppu, 0, 0), [255, 0, 0]);
+
+        ppu.lcd_regs.bgcnt[1].write(0);
+        ppu.draw_line(0);
+        assert_eq!(pixel(&ppu, 0, 0), [0, 0, 255]);
+    }
+
+    #[test]
+    fn draws_256_color_text_background() {
+        let mut ppu = ppu_with_text_bgs();
+        ppu.lcd_regs.dispcnt.write(0x0100);
+        // Tile 1 is now the second 64 byte tile, whose first row has colours 0x20 and 0x21.
+        ppu.write_simple::<u16, 2>(0x5000000 + 2 * 0x21, 0x001f);
+        ppu.write_simple::<u16, 2>(0x5000000 + 2 * 0x20, 0x03e0);
+        ppu.write_simple::<u8, 1>(0x6000000 + 64, 0x20);
+        ppu.write_simple::<u8, 1>(0x6000000 + 65, 0x21);
+        ppu.lcd_regs.bgcnt[0].write(1 << 7 | 1 << 8);
+        ppu.draw_line(0);
+        assert_eq!(pixel(&ppu, 0, 0), [0, 255, 0]);
+        assert_eq!(pixel(&ppu, 1, 0), [255, 0, 0]);
+        assert_eq!(pixel(&ppu, 2, 0), [0, 0, 255]);
+    }
 }
@@ -0,0 +1,268 @@
+use crate::utils::AddressableBits;
+
+use super::{Ppu, SCREEN_WIDTH};
+
+/// OBJ tiles are in the last 32 KiB of VRAM.
+const OBJ_TILE_BASE: usize = 0x10000;
+/// OBJ colours are in the second half of palette RAM, counted in colours.
+const OBJ_PALETTE: usize = 0x100;
+
+/// Width and height in pixels for each shape and size. Shape 3 is prohibited.
+const OBJ_SIZES: [[(u16, u16); 4]; 3] = [
+    // Square
+    [(8, 8), (16, 16), (32, 32), (64, 64)],
+    // Horizontal
+    [(16, 8), (32, 8), (32, 16), (64, 32)],
+    // Vertical
+    [(8, 16), (8, 32), (16, 32), (32, 64)],
+];
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ObjPixel {
+    pub color: [u8; 3],
+    pub priority: u16,
+}
+
+/// The three attributes of an OBJ in OAM. The fourth halfword belongs to the affine parameters.
+struct Obj {
+    attrs: [u16; 3],
+}
+
+impl Obj {
+    fn y(&self) -> u16 {
+        self.attrs[0].bits(0, 7)
+    }
+
+    fn affine(&self) -> bool {
+        self.attrs[0].bit(8) == 1
+    }
+
+    /// Regular OBJs with the double size bit set aren't displayed.
+    fn hidden(&self) -> bool {
+        !self.affine() && self.attrs[0].bit(9) == 1
+    }
+
+    /// Affine OBJs can be drawn in a box twice their size, so they aren't clipped when rotated.
+    fn double_size(&self) -> bool {
+        self.affine() && self.attrs[0].bit(9) == 1
+    }
+
+    /// Normal, semi-transparent, OBJ window or prohibited.
+    fn mode(&self) -> u16 {
+        self.attrs[0].bits(10, 11)
+    }
+
+    fn colors_256(&self) -> bool {
+        self.attrs[0].bit(13) == 1
+    }
+
+    fn size(&self) -> Option<(u16, u16)> {
+        let shape = usize::from(self.attrs[0].bits(14, 15));
+        let size = usize::from(self.attrs[1].bits(14, 15));
+        OBJ_SIZES.get(shape).map(|sizes| sizes[size])
+    }
+
+    /// X is 9 bits, where values past 255 are off the left of the screen.
+    fn x(&self) -> i16 {
+        let x = self.attrs[1].bits(0, 8) as i16;
+        if x >= 256 {
+            x - 512
+        } else {
+            x
+        }
+    }
+
+    /// Which of the 32 affine parameter groups an affine OBJ uses.
+    fn affine_index(&self) -> usize {
+        self.attrs[1].bits(9, 13).into()
+    }
+
+    fn flip_horizontal(&self) -> bool {
+        self.attrs[1].bit(12) == 1
+    }
+
+    fn flip_vertical(&self) -> bool {
+        self.attrs[1].bit(13) == 1
+    }
+
+    fn tile(&self) -> u16 {
+        self.attrs[2].bits(0, 9)
+    }
+
+    fn priority(&self) -> u16 {
+        self.attrs[2].bits(10, 11)
+    }
+
+    fn palette_bank(&self) -> u16 {
+        self.attrs[2].bits(12, 15)
+    }
+}
+
+impl Ppu {
+    fn obj(&self, index: usize) -> Obj {
+        let attr =
+            |i: usize| u16::from_le_bytes([self.oam[index * 8 + i], self.oam[index * 8 + i + 1]]);
+        Obj {
+            attrs: [attr(0), attr(2), attr(4)],
+        }
+    }
+
+    /// PA, PB, PC and PD of an affine parameter group, which are spread across the unused
+    /// fourth halfword of four OBJs.
+    fn obj_affine_params(&self, group: usize) -> [i32; 4] {
+        [0, 1, 2, 3].map(|i| {
+            let index = group * 32 + i * 8 + 6;
+            i16::from_le_bytes([self.oam[index], self.oam[index + 1]]).into()
+        })
+    }
+
+    /// Draw the OBJs on a line. Where OBJs overlap, the one with the highest priority is kept,
+    /// then the one earliest in OAM.
+    pub(super) fn obj_line(&self, y: u16) -> [Option<ObjPixel>; SCREEN_WIDTH as usize] {
+        let mut line = [None; SCREEN_WIDTH as usize];
+        let dispcnt = self.lcd_regs.dispcnt.read();
+        if dispcnt.bit(12) == 0 {
+            return line;
+        }
+
+        let mapping_1d = dispcnt.bit(6) == 1;
+        // The first half of OBJ tiles is used by the frame buffer in bitmap modes.
+        let bitmap_mode = self.bg_mode() >= 3;
+        // OBJs are drawn in the cycles of a line, fewer if HBlank is left free for OAM access.
+        let mut cycles_left: u16 = if dispcnt.bit(5) == 1 { 954 } else { 1210 };
+
+        for index in 0..128 {
+            let obj = self.obj(index);
+            if obj.hidden() {
+                continue;
+            }
+            let Some((width, height)) = obj.size() else {
+                continue;
+            };
+            let (box_width, box_height) = if obj.double_size() {
+                (2 * width, 2 * height)
+            } else {
+                (width, height)
+            };
+
+            // Y wraps around, so OBJs near the bottom of the 256 line range show at the top.
+            let row = y.wrapping_sub(obj.y()) & 0xff;
+            if row >= box_height {
+                continue;
+            }
+
+            // Every pixel of a regular OBJ takes a cycle, even off screen. Affine OBJs take two
+            // for each pixel of their box, plus 10 to set up.
+            let cycles = if obj.affine() {
+                10 + 2 * box_width
+            } else {
+                width
+            };
+            if cycles_left < cycles {
+                break;
+            }
+            cycles_left -= cycles;
+
+            // OBJ window masks still take their cycles, but aren't visible themselves.
+            if obj.mode() >= 2 || (bitmap_mode && obj.tile() < 512) {
+                continue;
+            }
+
+            let affine_params = obj
+                .affine()
+                .then(|| self.obj_affine_params(obj.affine_index()));
+            for dx in 0..box_width {
+                let screen_x = obj.x() + dx as i16;
+                if !(0..SCREEN_WIDTH as i16).contains(&screen_x) {
+                    continue;
+                }
+
+                let (tile_x, tile_y) = match affine_params {
+                    // The matrix maps from the centre of the box to the centre of the OBJ.
+                    Some([pa, pb, pc, pd]) => {
+                        let box_x = i32::from(dx) - i32::from(box_width / 2);
+                        let box_y = i32::from(row) - i32::from(box_height / 2);
+                        let tile_x = ((pa * box_x + pb * box_y) >> 8) + i32::from(width / 2);
+                        let tile_y = ((pc * box_x + pd * box_y) >> 8) + i32::from(height / 2);
+                        if !(0..i32::from(width)).contains(&tile_x)
+                            || !(0..i32::from(height)).contains(&tile_y)
+                        {
+                            continue;
+                        }
+                        (tile_x as u16, tile_y as u16)
+                    }
+                    None => {
+                        let tile_x = if obj.flip_horizontal() {
+                            width - 1 - dx
+                        } else {
+                            dx
+                        };
+                        let tile_y = if obj.flip_vertical() {
+                            height - 1 - row
+                        } else {
+                            row
+                        };
+                        (tile_x, tile_y)
+                    }
+                };
+
+                let Some(color) = self.obj_color(&obj, tile_x, tile_y, width, mapping_1d) else {
+                    continue;
+                };
+                let pixel = &mut line[screen_x as usize];
+                if !matches!(pixel, Some(p) if p.priority <= obj.priority()) {
+                    *pixel = Some(ObjPixel {
+                        color,
+                        priority: obj.priority(),
+                    });
+                }
+            }
+        }
+
+        line
+    }
+
+    /// Look up a pixel within an OBJ, returning `None` if it's transparent.
+    fn obj_color(
+        &self,
+        obj: &Obj,
+        x: u16,
+        y: u16,
+        width: u16,
+        mapping_1d: bool,
+    ) -> Option<[u8; 3]> {
+        // Tile numbers count in 32 byte units, and 256 colour tiles take two.
+        let tile_units = if obj.colors_256() { 2 } else { 1 };
+        // With 2D mapping, OBJ tiles are laid out in a 32x32 grid.
+        let row_units = if mapping_1d {
+            width / 8 * tile_units
+        } else {
+            32
+        };
+        let tile = obj.tile() + y / 8 * row_units + x / 8 * tile_units;
+
+        let (x, y) = (usize::from(x % 8), usize::from(y % 8));
+        let tile_offset = usize::from(tile) * 32;
+        let vram_index = |offset: usize| OBJ_TILE_BASE + ((tile_offset + offset) & 0x7fff);
+
+        let palette_index = if obj.colors_256() {
+            usize::from(self.vram[vram_index(y * 8 + x)])
+        } else {
+            let byte = self.vram[vram_index(y * 4 + x / 2)];
+            let offset = if x % 2 == 0 {
+                byte.bits(0, 3)
+            } else {
+                byte.bits(4, 7)
+            };
+            if offset == 0 {
+                return None;
+            }
+            usize::from(obj.palette_bank()) * 16 + usize::from(offset)
+        };
+
+        if palette_index == 0 {
+            return None;
+        }
+        Some(self.palette_lookup_256(OBJ_PALETTE + palette_index))
+    }
+}
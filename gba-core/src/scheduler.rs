@@ -0,0 +1,100 @@
+/// Something which happens at a known cycle, so the CPU can run without checking for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The PPU reaches the end of the visible part of a scanline.
+    HBlank,
+    /// The PPU starts the next scanline.
+    HDraw,
+    TimerOverflow(usize),
+    /// A DMA channel is ready to transfer.
+    Dma,
+    /// IE, IF or IME changed while an enabled interrupt was requested.
+    Irq,
+}
+
+/// A queue of events ordered by the cycle they're due at.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    now: u64,
+    // Sorted by time, with events due at the same time kept in the order they were scheduled.
+    events: Vec<(u64, Event)>,
+}
+
+impl Scheduler {
+    /// Cycles since power on.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn advance(&mut self, cycles: u64) {
+        self.now += cycles;
+    }
+
+    /// Schedule an event some cycles from now, replacing it if it was already scheduled.
+    pub fn schedule(&mut self, event: Event, delay: u64) {
+        self.schedule_at(event, self.now + delay);
+    }
+
+    /// Schedule an event at an absolute time, replacing it if it was already scheduled. Times in
+    /// the past are due immediately.
+    pub fn schedule_at(&mut self, event: Event, time: u64) {
+        self.cancel(event);
+        let index = self.events.partition_point(|&(t, _)| t <= time);
+        self.events.insert(index, (time, event));
+    }
+
+    pub fn cancel(&mut self, event: Event) {
+        self.events.retain(|&(_, e)| e != event);
+    }
+
+    /// The time of the earliest event, or `u64::MAX` if nothing is scheduled.
+    pub fn next_event_time(&self) -> u64 {
+        self.events.first().map_or(u64::MAX, |&(time, _)| time)
+    }
+
+    /// Remove and return the earliest event if it is due, along with the time it was due at.
+    pub fn pop_due(&mut self) -> Option<(Event, u64)> {
+        match self.events.first() {
+            Some(&(time, event)) if time <= self.now => {
+                self.events.remove(0);
+                Some((event, time))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_due_in_time_order() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(Event::HDraw, 20);
+        scheduler.schedule(Event::HBlank, 10);
+        scheduler.schedule(Event::Dma, 10);
+        assert_eq!(scheduler.next_event_time(), 10);
+        assert_eq!(scheduler.pop_due(), None);
+
+        scheduler.advance(15);
+        assert_eq!(scheduler.pop_due(), Some((Event::HBlank, 10)));
+        assert_eq!(scheduler.pop_due(), Some((Event::Dma, 10)));
+        assert_eq!(scheduler.pop_due(), None);
+        assert_eq!(scheduler.next_event_time(), 20);
+    }
+
+    #[test]
+    fn rescheduling_replaces_event() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(Event::TimerOverflow(0), 5);
+        scheduler.schedule(Event::TimerOverflow(1), 8);
+        scheduler.schedule(Event::TimerOverflow(0), 100);
+        assert_eq!(scheduler.next_event_time(), 8);
+
+        scheduler.cancel(Event::TimerOverflow(1));
+        assert_eq!(scheduler.next_event_time(), 100);
+        scheduler.cancel(Event::TimerOverflow(0));
+        assert_eq!(scheduler.next_event_time(), u64::MAX);
+    }
+}
@@ -51,9 +51,17 @@ impl GbaThread {
                         self.control_state.update(event);
                     }
                     Request::LoadRom(rom) => {
-                        self.gba = GbaCore::default();
-                        self.gba.load_rom(&rom);
-                        self.gba.skip_bios();
+                        // Keep running the current game if the new ROM can't be loaded.
+                        let mut gba = GbaCore::default();
+                        match gba.load_rom(&rom) {
+                            Ok(()) => {
+                                gba.skip_bios();
+                                self.gba = gba;
+                            }
+                            Err(err) => {
+                                console::error_1(&format!("Failed to load ROM: {}", err).into());
+                            }
+                        }
                     }
                     Request::KeyEvent { key, pressed } => {
                         self.gba.set_key(key, pressed);